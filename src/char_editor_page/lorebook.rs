@@ -0,0 +1,537 @@
+use iced::{
+    Alignment, Element,
+    Length::{Fill, FillPortion},
+    widget::{
+        Column, TextEditor, checkbox, column, container, mouse_area, pick_list, row, scrollable,
+        text_editor::{Action, Content},
+        text_input,
+    },
+};
+use serde_json::Value;
+
+use crate::{
+    AppCommand,
+    char_editor_page::CharEditorCommand,
+    persona::card_v3::{Lorebook, LorebookEntry},
+    settings::Settings,
+    utils::{
+        tokens,
+        widgets::{bold_text, button, text},
+    },
+};
+
+/// Insertion positions of the V2 spec, relative to the character definition.
+const POSITIONS: [&str; 2] = ["before_char", "after_char"];
+
+#[derive(Debug, Clone)]
+pub enum LorebookCommand {
+    BookName(String),
+    ScanDepth(String),
+    TokenBudget(String),
+    RecursiveScanning(bool),
+
+    Search(String),
+    Select(usize),
+    Add,
+    Duplicate(usize),
+    Delete(usize),
+    DragStart(usize),
+    DragOver(usize),
+    DragEnd,
+
+    Name(String),
+    Keys(String),
+    SecondaryKeys(String),
+    Selective(bool),
+    Constant(bool),
+    CaseSensitive(bool),
+    Enabled(bool),
+    InsertionOrder(String),
+    Priority(String),
+    Position(&'static str),
+    Content(Action),
+}
+
+impl From<LorebookCommand> for AppCommand {
+    fn from(lorebook_command: LorebookCommand) -> Self {
+        CharEditorCommand::Lorebook(lorebook_command).into()
+    }
+}
+
+/// Edits the entries of a character book. Number fields keep what was typed
+/// and only apply it once it parses.
+pub struct LorebookEditor {
+    book: Lorebook,
+    /// Whether the card had a book, an empty one is only kept in that case.
+    had_book: bool,
+    scan_depth: String,
+    token_budget: String,
+    search: String,
+    selected: Option<usize>,
+    /// Entry being dragged to a new place in the list.
+    dragging: Option<usize>,
+    keys: String,
+    secondary_keys: String,
+    insertion_order: String,
+    priority: String,
+    content: Content,
+}
+
+impl LorebookEditor {
+    pub fn new(book: Option<Lorebook>) -> Self {
+        let had_book = book.is_some();
+        let book = book.unwrap_or_default();
+        let mut editor = Self {
+            had_book,
+            scan_depth: book.scan_depth.map(|d| d.to_string()).unwrap_or_default(),
+            token_budget: book.token_budget.map(|b| b.to_string()).unwrap_or_default(),
+            book,
+            search: String::new(),
+            selected: None,
+            dragging: None,
+            keys: String::new(),
+            secondary_keys: String::new(),
+            insertion_order: String::new(),
+            priority: String::new(),
+            content: Content::new(),
+        };
+        if !editor.book.entries.is_empty() {
+            editor.select(0);
+        }
+        editor
+    }
+
+    /// The edited book, `None` when there was none and nothing was added.
+    pub fn book(&self) -> Option<Lorebook> {
+        (self.had_book || !self.book.entries.is_empty()).then(|| self.book.clone())
+    }
+
+    pub fn len(&self) -> usize {
+        self.book.entries.len()
+    }
+
+    /// Fields holding text that isn't a valid value.
+    pub fn errors(&self) -> Vec<String> {
+        let mut errors = vec![];
+        for (label, value) in [
+            ("Scan depth", &self.scan_depth),
+            ("Token budget", &self.token_budget),
+            ("Priority", &self.priority),
+        ] {
+            if Self::optional_number(value).is_none() {
+                errors.push(format!("{label} must be a number"));
+            }
+        }
+        if self.selected.is_some() && self.insertion_order.trim().parse::<i32>().is_err() {
+            errors.push("Insertion order must be a number".to_string());
+        }
+        errors
+    }
+
+    pub fn update(&mut self, command: LorebookCommand) {
+        match command {
+            LorebookCommand::BookName(name) => {
+                self.book.name = Some(name).filter(|n| !n.is_empty())
+            }
+            LorebookCommand::ScanDepth(depth) => {
+                if let Some(depth) = Self::optional_number(&depth) {
+                    self.book.scan_depth = depth;
+                }
+                self.scan_depth = depth;
+            }
+            LorebookCommand::TokenBudget(budget) => {
+                if let Some(budget) = Self::optional_number(&budget) {
+                    self.book.token_budget = budget;
+                }
+                self.token_budget = budget;
+            }
+            LorebookCommand::RecursiveScanning(recursive) => {
+                self.book.recursive_scanning = Some(recursive)
+            }
+            LorebookCommand::Search(search) => self.search = search,
+            LorebookCommand::Select(idx) => self.select(idx),
+            LorebookCommand::Add => {
+                self.book.entries.push(LorebookEntry::new(self.next_id()));
+                self.select(self.book.entries.len() - 1);
+            }
+            LorebookCommand::Duplicate(idx) => {
+                let mut entry = self.book.entries[idx].clone();
+                entry.id = self.next_id();
+                self.book.entries.insert(idx + 1, entry);
+                self.select(idx + 1);
+            }
+            LorebookCommand::Delete(idx) => {
+                self.book.entries.remove(idx);
+                match self.selected {
+                    Some(selected) if selected == idx => {
+                        self.selected = None;
+                        if idx < self.book.entries.len() {
+                            self.select(idx)
+                        } else if idx > 0 {
+                            self.select(idx - 1)
+                        }
+                    }
+                    Some(selected) if selected > idx => self.selected = Some(selected - 1),
+                    _ => (),
+                }
+            }
+            LorebookCommand::DragStart(idx) => self.dragging = Some(idx),
+            LorebookCommand::DragOver(idx) => {
+                if let Some(from) = self.dragging
+                    && from != idx
+                {
+                    self.move_entry(from, idx);
+                    self.dragging = Some(idx);
+                }
+            }
+            LorebookCommand::DragEnd => self.dragging = None,
+            command => {
+                if let Some(idx) = self.selected {
+                    self.update_entry(idx, command)
+                }
+            }
+        }
+    }
+
+    fn update_entry(&mut self, idx: usize, command: LorebookCommand) {
+        let entry = &mut self.book.entries[idx];
+        match command {
+            LorebookCommand::Name(name) => entry.comment = Some(name).filter(|n| !n.is_empty()),
+            LorebookCommand::Keys(keys) => {
+                entry.keys = Self::split_keys(&keys);
+                self.keys = keys;
+            }
+            LorebookCommand::SecondaryKeys(keys) => {
+                entry.secondary_keys = Some(Self::split_keys(&keys)).filter(|k| !k.is_empty());
+                self.secondary_keys = keys;
+            }
+            LorebookCommand::Selective(selective) => entry.selective = Some(selective),
+            LorebookCommand::Constant(constant) => entry.constant = Some(constant),
+            LorebookCommand::CaseSensitive(case_sensitive) => {
+                entry.case_sensitive = Some(case_sensitive)
+            }
+            LorebookCommand::Enabled(enabled) => entry.enabled = enabled,
+            LorebookCommand::InsertionOrder(order) => {
+                if let Ok(order) = order.trim().parse() {
+                    entry.insertion_order = order;
+                }
+                self.insertion_order = order;
+            }
+            LorebookCommand::Priority(priority) => {
+                if let Some(priority) = Self::optional_number(&priority) {
+                    entry.priority = priority;
+                }
+                self.priority = priority;
+            }
+            LorebookCommand::Position(position) => entry.position = Some(position.to_string()),
+            LorebookCommand::Content(action) => {
+                self.content.perform(action);
+                let text = self.content.text();
+                entry.content = text.strip_suffix('\n').unwrap_or(&text).to_string();
+            }
+            _ => (),
+        }
+    }
+
+    /// Loads the entry's fields into the text inputs.
+    fn select(&mut self, idx: usize) {
+        let entry = &self.book.entries[idx];
+        self.keys = entry.keys.join(", ");
+        self.secondary_keys = entry.secondary_keys.clone().unwrap_or_default().join(", ");
+        self.insertion_order = entry.insertion_order.to_string();
+        self.priority = entry.priority.map(|p| p.to_string()).unwrap_or_default();
+        self.content = Content::with_text(&entry.content);
+        self.selected = Some(idx);
+    }
+
+    fn move_entry(&mut self, from: usize, to: usize) {
+        let entry = self.book.entries.remove(from);
+        self.book.entries.insert(to, entry);
+        self.selected = self.selected.map(|selected| match selected {
+            s if s == from => to,
+            s if from < s && s <= to => s - 1,
+            s if to <= s && s < from => s + 1,
+            s => s,
+        });
+    }
+
+    /// One more than the highest numeric id, entries without one stay without.
+    fn next_id(&self) -> Option<Value> {
+        let ids = self.book.entries.iter().filter_map(|e| e.id.as_ref());
+        match ids.clone().all(Value::is_i64) {
+            true => Some(
+                ids.filter_map(Value::as_i64)
+                    .max()
+                    .map_or(0, |id| id + 1)
+                    .into(),
+            ),
+            false => None,
+        }
+    }
+
+    fn split_keys(keys: &str) -> Vec<String> {
+        keys.split(',')
+            .map(str::trim)
+            .filter(|k| !k.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Empty text is no value, `None` is text that doesn't parse.
+    fn optional_number(value: &str) -> Option<Option<i32>> {
+        match value.trim() {
+            "" => Some(None),
+            value => value.parse().ok().map(Some),
+        }
+    }
+
+    fn title(idx: usize, entry: &LorebookEntry) -> String {
+        entry
+            .comment
+            .clone()
+            .or(entry.name.clone())
+            .filter(|t| !t.is_empty())
+            .or(entry.keys.first().cloned())
+            .unwrap_or_else(|| format!("Entry {}", idx + 1))
+    }
+
+    fn matches(&self, idx: usize, entry: &LorebookEntry) -> bool {
+        let search = self.search.trim().to_lowercase();
+        search.is_empty()
+            || Self::title(idx, entry).to_lowercase().contains(&search)
+            || entry
+                .keys
+                .iter()
+                .any(|k| k.to_lowercase().contains(&search))
+            || entry.content.to_lowercase().contains(&search)
+    }
+
+    pub fn view<'a>(&'a self, settings: &'a Settings) -> Element<'a, AppCommand> {
+        let input = |label: &'a str, value: &'a str, on_input: fn(String) -> LorebookCommand| {
+            column![
+                bold_text(label, settings),
+                text_input(label, value)
+                    .size(settings.font_size())
+                    .on_input(move |v| on_input(v).into())
+            ]
+            .spacing(5)
+        };
+        let toggle = |label: &'a str, value: bool, on_toggle: fn(bool) -> LorebookCommand| {
+            checkbox(label, value)
+                .size(settings.font_size())
+                .text_size(settings.font_size())
+                .on_toggle(move |t| on_toggle(t).into())
+        };
+
+        let book = row![
+            input(
+                "Book name",
+                self.book.name.as_deref().unwrap_or_default(),
+                LorebookCommand::BookName
+            ),
+            input("Scan depth", &self.scan_depth, LorebookCommand::ScanDepth),
+            input(
+                "Token budget",
+                &self.token_budget,
+                LorebookCommand::TokenBudget
+            ),
+            toggle(
+                "Recursive scanning",
+                self.book.recursive_scanning.unwrap_or_default(),
+                LorebookCommand::RecursiveScanning
+            ),
+        ]
+        .align_y(Alignment::End)
+        .spacing(10);
+
+        let mut list = Column::new().spacing(5);
+        for (idx, entry) in self.book.entries.iter().enumerate() {
+            if !self.matches(idx, entry) {
+                continue;
+            }
+            let mut title = iced::widget::button(text(Self::title(idx, entry), settings))
+                .width(Fill)
+                .on_press(LorebookCommand::Select(idx).into());
+            if self.selected != Some(idx) {
+                title = title.style(iced::widget::button::secondary);
+            }
+            list = list.push(
+                mouse_area(
+                    row![
+                        mouse_area(text("≡", settings))
+                            .on_press(LorebookCommand::DragStart(idx).into()),
+                        title,
+                        button("Duplicate", settings)
+                            .on_press(LorebookCommand::Duplicate(idx).into()),
+                        button("Delete", settings).on_press(LorebookCommand::Delete(idx).into()),
+                    ]
+                    .align_y(Alignment::Center)
+                    .spacing(5),
+                )
+                .on_enter(LorebookCommand::DragOver(idx).into()),
+            );
+        }
+        let list = column![
+            text_input("Search entries", &self.search)
+                .size(settings.font_size())
+                .on_input(|s| LorebookCommand::Search(s).into()),
+            scrollable(
+                mouse_area(list)
+                    .on_release(LorebookCommand::DragEnd.into())
+                    .on_exit(LorebookCommand::DragEnd.into())
+            )
+            .height(Fill),
+            button("Add entry", settings).on_press(LorebookCommand::Add.into()),
+        ]
+        .spacing(10)
+        .width(FillPortion(1));
+
+        let entry: Element<'a, AppCommand> = match self.selected {
+            Some(idx) => {
+                let entry = &self.book.entries[idx];
+                column![
+                    input(
+                        "Name",
+                        entry.comment.as_deref().unwrap_or_default(),
+                        LorebookCommand::Name
+                    ),
+                    input("Keys, comma separated", &self.keys, LorebookCommand::Keys),
+                    input(
+                        "Secondary keys",
+                        &self.secondary_keys,
+                        LorebookCommand::SecondaryKeys
+                    ),
+                    row![
+                        toggle("Enabled", entry.enabled, LorebookCommand::Enabled),
+                        toggle(
+                            "Selective",
+                            entry.selective.unwrap_or_default(),
+                            LorebookCommand::Selective
+                        ),
+                        toggle(
+                            "Constant",
+                            entry.constant.unwrap_or_default(),
+                            LorebookCommand::Constant
+                        ),
+                        toggle(
+                            "Case sensitive",
+                            entry.case_sensitive.unwrap_or_default(),
+                            LorebookCommand::CaseSensitive
+                        ),
+                    ]
+                    .spacing(10),
+                    row![
+                        input(
+                            "Insertion order",
+                            &self.insertion_order,
+                            LorebookCommand::InsertionOrder
+                        ),
+                        input("Priority", &self.priority, LorebookCommand::Priority),
+                        column![
+                            bold_text("Position", settings),
+                            pick_list(
+                                POSITIONS,
+                                POSITIONS
+                                    .into_iter()
+                                    .find(|p| entry.position.as_deref() == Some(p)),
+                                |p| LorebookCommand::Position(p).into()
+                            )
+                            .text_size(settings.font_size())
+                        ]
+                        .spacing(5),
+                    ]
+                    .spacing(10),
+                    row![
+                        bold_text("Content", settings),
+                        text(
+                            format!("~{} tokens", tokens::estimate(&entry.content)),
+                            settings
+                        )
+                    ]
+                    .spacing(10),
+                    TextEditor::new(&self.content)
+                        .size(settings.font_size())
+                        .height(Fill)
+                        .on_action(|a| LorebookCommand::Content(a).into()),
+                ]
+                .spacing(10)
+                .into()
+            }
+            None => text("No entry selected", settings),
+        };
+
+        column![
+            book,
+            row![list, container(entry).width(FillPortion(2))]
+                .spacing(10)
+                .height(Fill)
+        ]
+        .spacing(10)
+        .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::{LorebookCommand, LorebookEditor};
+    use crate::persona::card_v3::{Lorebook, LorebookEntry};
+
+    fn editor(ids: &[i64]) -> LorebookEditor {
+        LorebookEditor::new(Some(Lorebook {
+            entries: ids
+                .iter()
+                .map(|id| LorebookEntry::new(Some(Value::from(*id))))
+                .collect(),
+            ..Default::default()
+        }))
+    }
+
+    fn ids(editor: &LorebookEditor) -> Vec<i64> {
+        editor
+            .book
+            .entries
+            .iter()
+            .map(|e| e.id.as_ref().and_then(Value::as_i64).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn dragging_moves_the_entry_and_keeps_the_selection() {
+        let mut editor = editor(&[1, 2, 3]);
+        editor.update(LorebookCommand::Select(1));
+        editor.update(LorebookCommand::DragStart(0));
+        editor.update(LorebookCommand::DragOver(1));
+        editor.update(LorebookCommand::DragOver(2));
+        editor.update(LorebookCommand::DragEnd);
+        // Entering a row after the drag ended moves nothing.
+        editor.update(LorebookCommand::DragOver(0));
+        assert_eq!(ids(&editor), [2, 3, 1]);
+        assert_eq!(editor.selected, Some(0));
+    }
+
+    #[test]
+    fn duplicates_get_a_new_id() {
+        let mut editor = editor(&[4, 7]);
+        editor.update(LorebookCommand::Duplicate(0));
+        editor.update(LorebookCommand::Delete(2));
+        assert_eq!(ids(&editor), [4, 8]);
+        assert_eq!(editor.selected, Some(1));
+    }
+
+    #[test]
+    fn entry_fields_are_written_to_the_selected_entry() {
+        let mut editor = LorebookEditor::new(None);
+        assert!(editor.book().is_none());
+        editor.update(LorebookCommand::Add);
+        editor.update(LorebookCommand::Keys("castle, moat ,".to_string()));
+        editor.update(LorebookCommand::Priority("x".to_string()));
+        assert_eq!(editor.errors(), ["Priority must be a number"]);
+        editor.update(LorebookCommand::Priority("5".to_string()));
+        let book = editor.book().unwrap();
+        assert_eq!(book.entries[0].keys, ["castle", "moat"]);
+        assert_eq!(book.entries[0].priority, Some(5));
+        assert!(editor.errors().is_empty());
+    }
+}
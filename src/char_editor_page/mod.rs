@@ -0,0 +1,486 @@
+use std::fs;
+
+use anyhow::Result;
+use chrono::Local;
+use iced::{
+    Border, Element,
+    Length::Fill,
+    Theme,
+    widget::{
+        Column, TextEditor, column, container,
+        image::Handle,
+        row, scrollable,
+        text_editor::{Action, Content},
+        text_input,
+    },
+};
+use iced_modern_theme::colors::colors;
+use log::trace;
+
+mod lorebook;
+
+use crate::{
+    AppCommand,
+    char_editor_page::lorebook::{LorebookCommand, LorebookEditor},
+    chat_page::session::SessionLoader,
+    persona::{
+        Persona,
+        card_v3::{CardV3, CharacterDataV3},
+        loader::PersonaLoader,
+    },
+    settings::Settings,
+    utils::{
+        files, tokens,
+        widgets::{bold_text, button, text},
+    },
+};
+
+/// The long text fields of a card, each edited in its own multi-line editor.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Field {
+    Description,
+    Personality,
+    Scenario,
+    FirstMes,
+    MesExample,
+    SystemPrompt,
+    PostHistoryInstructions,
+    CreatorNotes,
+}
+
+impl Field {
+    const ALL: [Field; 8] = [
+        Field::Description,
+        Field::Personality,
+        Field::Scenario,
+        Field::FirstMes,
+        Field::MesExample,
+        Field::SystemPrompt,
+        Field::PostHistoryInstructions,
+        Field::CreatorNotes,
+    ];
+
+    fn label(&self) -> &'static str {
+        match self {
+            Field::Description => "Description",
+            Field::Personality => "Personality",
+            Field::Scenario => "Scenario",
+            Field::FirstMes => "First message",
+            Field::MesExample => "Example messages",
+            Field::SystemPrompt => "System prompt",
+            Field::PostHistoryInstructions => "Post history instructions",
+            Field::CreatorNotes => "Creator notes",
+        }
+    }
+
+    fn get<'a>(&self, data: &'a CharacterDataV3) -> &'a str {
+        match self {
+            Field::Description => &data.description,
+            Field::Personality => &data.personality,
+            Field::Scenario => &data.scenario,
+            Field::FirstMes => &data.first_mes,
+            Field::MesExample => &data.mes_example,
+            Field::SystemPrompt => &data.system_prompt,
+            Field::PostHistoryInstructions => &data.post_history_instructions,
+            Field::CreatorNotes => &data.creator_notes,
+        }
+    }
+
+    fn set(&self, data: &mut CharacterDataV3, value: String) {
+        *match self {
+            Field::Description => &mut data.description,
+            Field::Personality => &mut data.personality,
+            Field::Scenario => &mut data.scenario,
+            Field::FirstMes => &mut data.first_mes,
+            Field::MesExample => &mut data.mes_example,
+            Field::SystemPrompt => &mut data.system_prompt,
+            Field::PostHistoryInstructions => &mut data.post_history_instructions,
+            Field::CreatorNotes => &mut data.creator_notes,
+        } = value;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Greetings {
+    Alternate,
+    GroupOnly,
+}
+
+#[derive(Debug, Clone)]
+pub enum CharEditorCommand {
+    Name(String),
+    Nickname(String),
+    Creator(String),
+    Version(String),
+    Field(Field, Action),
+    Greeting(Greetings, usize, Action),
+    AddGreeting(Greetings),
+    RemoveGreeting(Greetings, usize),
+    Tags(Action),
+    AvatarPath(String),
+    ReplaceAvatar,
+    ToggleLorebook,
+    Lorebook(LorebookCommand),
+    Save,
+    Cancel,
+}
+
+impl From<CharEditorCommand> for AppCommand {
+    fn from(char_editor_command: CharEditorCommand) -> Self {
+        AppCommand::CharEditorCommand(char_editor_command)
+    }
+}
+
+pub struct CharEditorPage {
+    char: Persona,
+    /// The card being edited, holding the fields without an editor untouched.
+    card: CardV3,
+    name: String,
+    nickname: String,
+    creator: String,
+    version: String,
+    fields: Vec<(Field, Content)>,
+    alternate_greetings: Vec<Content>,
+    group_greetings: Vec<Content>,
+    /// One tag per line.
+    tags: Content,
+    avatar_path: String,
+    /// PNG data and preview of the replacement avatar.
+    avatar: Option<(Vec<u8>, Handle)>,
+    avatar_error: Option<String>,
+    lorebook: LorebookEditor,
+    show_lorebook: bool,
+    /// Names of the other characters, to refuse duplicates.
+    taken_names: Vec<String>,
+}
+
+impl CharEditorPage {
+    pub fn new(char: Persona, taken_names: Vec<String>) -> Self {
+        let card = char.card_v3();
+        let data = &card.data;
+        Self {
+            name: data.name.clone(),
+            nickname: data.nickname.clone().unwrap_or_default(),
+            creator: data.creator.clone(),
+            version: data.character_version.clone(),
+            fields: Field::ALL
+                .into_iter()
+                .map(|field| (field, Content::with_text(field.get(data))))
+                .collect(),
+            alternate_greetings: data
+                .alternate_greetings
+                .iter()
+                .map(|g| Content::with_text(g))
+                .collect(),
+            group_greetings: data
+                .group_only_greetings
+                .iter()
+                .map(|g| Content::with_text(g))
+                .collect(),
+            tags: Content::with_text(&data.tags.join("\n")),
+            avatar_path: String::new(),
+            avatar: None,
+            avatar_error: None,
+            lorebook: LorebookEditor::new(data.character_book.clone()),
+            show_lorebook: false,
+            taken_names,
+            card,
+            char,
+        }
+    }
+
+    pub fn update(&mut self, command: CharEditorCommand) {
+        match command {
+            CharEditorCommand::Name(name) => self.name = name,
+            CharEditorCommand::Nickname(nickname) => self.nickname = nickname,
+            CharEditorCommand::Creator(creator) => self.creator = creator,
+            CharEditorCommand::Version(version) => self.version = version,
+            CharEditorCommand::Field(field, action) => {
+                if let Some((_, content)) = self.fields.iter_mut().find(|(f, _)| *f == field) {
+                    content.perform(action)
+                }
+            }
+            CharEditorCommand::Greeting(greetings, idx, action) => {
+                if let Some(content) = self.greetings_mut(greetings).get_mut(idx) {
+                    content.perform(action)
+                }
+            }
+            CharEditorCommand::AddGreeting(greetings) => {
+                self.greetings_mut(greetings).push(Content::new())
+            }
+            CharEditorCommand::RemoveGreeting(greetings, idx) => {
+                let greetings = self.greetings_mut(greetings);
+                if idx < greetings.len() {
+                    greetings.remove(idx);
+                }
+            }
+            CharEditorCommand::Tags(action) => self.tags.perform(action),
+            CharEditorCommand::AvatarPath(path) => self.avatar_path = path,
+            CharEditorCommand::ReplaceAvatar => match fs::read(self.avatar_path.trim())
+                .map_err(anyhow::Error::from)
+                .and_then(|data| PersonaLoader::to_png(&data))
+            {
+                Ok(avatar) => {
+                    self.avatar = Some((avatar.clone(), Handle::from_bytes(avatar)));
+                    self.avatar_error = None;
+                }
+                Err(e) => self.avatar_error = Some(e.to_string()),
+            },
+            CharEditorCommand::ToggleLorebook => self.show_lorebook = !self.show_lorebook,
+            CharEditorCommand::Lorebook(lorebook_command) => self.lorebook.update(lorebook_command),
+            // Handled by the app, which owns the other pages.
+            CharEditorCommand::Save | CharEditorCommand::Cancel => (),
+        }
+    }
+
+    /// Writes the card back to the character's directory, moving its chats
+    /// along when it was renamed. Returns the reloaded character.
+    pub fn save(&self) -> Result<Persona> {
+        let card = self.card();
+        let saved = self
+            .char
+            .save(&card, self.avatar.as_ref().map(|(png, _)| png.as_slice()))?;
+        SessionLoader::rename_char(self.char.name(), saved.name())?;
+        trace!("Saved {}", saved.name());
+        Ok(saved)
+    }
+
+    /// The edited card, unknown fields and extensions kept from the original.
+    fn card(&self) -> CardV3 {
+        let mut card = self.card.clone();
+        let data = &mut card.data;
+        data.name = self.name.trim().to_string();
+        data.nickname = Some(self.nickname.trim().to_string()).filter(|n| !n.is_empty());
+        data.creator = self.creator.trim().to_string();
+        data.character_version = self.version.trim().to_string();
+        for (field, content) in &self.fields {
+            field.set(data, Self::value(content));
+        }
+        data.alternate_greetings = self.alternate_greetings.iter().map(Self::value).collect();
+        data.group_only_greetings = self.group_greetings.iter().map(Self::value).collect();
+        data.tags = Self::value(&self.tags)
+            .lines()
+            .map(str::trim)
+            .filter(|t| !t.is_empty())
+            .map(str::to_string)
+            .collect();
+        data.character_book = self.lorebook.book();
+        data.modification_date = Some(Local::now().timestamp());
+        card
+    }
+
+    /// Problems that prevent saving.
+    fn errors(&self) -> Vec<String> {
+        let name = self.name.trim();
+        let mut errors = vec![];
+        if name.is_empty() {
+            errors.push("The name is required".to_string());
+        }
+        if !name.is_empty() && files::file_name(name) != name {
+            errors.push(
+                "The name can't be . or .., end with a dot or contain / \\ : * ? \" < > | \
+                 or control characters"
+                    .to_string(),
+            );
+        }
+        if self
+            .taken_names
+            .iter()
+            .any(|taken| taken.eq_ignore_ascii_case(name))
+        {
+            errors.push(format!("Another character is already named {name}"));
+        }
+        errors.extend(self.lorebook.errors());
+        errors
+    }
+
+    /// Problems worth pointing out that still make a usable card.
+    fn warnings(&self) -> Vec<String> {
+        let mut warnings = vec![];
+        // Same test as CardV3::needs_v3, without building the card every frame.
+        if self.char.spec() != CardV3::SPEC
+            && (!self.nickname.trim().is_empty() || !self.group_greetings.is_empty())
+        {
+            warnings.push(
+                "Saved as a V3 card, V2 cards have no nickname or group only greetings".to_string(),
+            );
+        }
+        if let Some(e) = &self.avatar_error {
+            warnings.push(format!("Avatar not replaced: {e}"));
+        }
+        if self.fields.iter().any(|(field, content)| {
+            *field == Field::FirstMes && Self::value(content).trim().is_empty()
+        }) {
+            warnings.push("Without a first message chats start empty".to_string());
+        }
+        if self
+            .alternate_greetings
+            .iter()
+            .chain(&self.group_greetings)
+            .any(|g| Self::value(g).trim().is_empty())
+        {
+            warnings.push("Empty greetings are never used".to_string());
+        }
+        warnings
+    }
+
+    fn greetings_mut(&mut self, greetings: Greetings) -> &mut Vec<Content> {
+        match greetings {
+            Greetings::Alternate => &mut self.alternate_greetings,
+            Greetings::GroupOnly => &mut self.group_greetings,
+        }
+    }
+
+    /// The editor's text without the newline `Content::text` always ends with.
+    fn value(content: &Content) -> String {
+        let text = content.text();
+        text.strip_suffix('\n').unwrap_or(&text).to_string()
+    }
+
+    pub fn view<'a>(&'a self, settings: &'a Settings) -> Element<'a, AppCommand> {
+        let line = |label: &'a str, value: &'a str, on_input: fn(String) -> CharEditorCommand| {
+            column![
+                bold_text(label, settings),
+                text_input(label, value)
+                    .size(settings.font_size())
+                    .on_input(move |v| on_input(v).into())
+            ]
+            .spacing(5)
+        };
+        let editor = |label: String,
+                      content: &'a Content,
+                      on_action: Box<dyn Fn(Action) -> AppCommand + 'a>| {
+            column![
+                row![
+                    bold_text(label, settings),
+                    text(
+                        format!("~{} tokens", tokens::estimate(&Self::value(content))),
+                        settings
+                    )
+                ]
+                .spacing(10),
+                TextEditor::new(content)
+                    .size(settings.font_size())
+                    .on_action(on_action)
+            ]
+            .spacing(5)
+        };
+
+        let avatar = match &self.avatar {
+            Some((_, handle)) => iced::widget::image(handle),
+            None => self.char.image(),
+        };
+        let header = row![
+            avatar.height(200),
+            column![
+                line("Name", &self.name, CharEditorCommand::Name),
+                line("Nickname", &self.nickname, CharEditorCommand::Nickname),
+                row![
+                    line("Creator", &self.creator, CharEditorCommand::Creator),
+                    line("Version", &self.version, CharEditorCommand::Version),
+                ]
+                .spacing(10),
+                row![
+                    text_input("/path/to/avatar.png", &self.avatar_path)
+                        .size(settings.font_size())
+                        .on_input(|p| CharEditorCommand::AvatarPath(p).into())
+                        .on_submit(CharEditorCommand::ReplaceAvatar.into()),
+                    button("Replace avatar", settings)
+                        .on_press(CharEditorCommand::ReplaceAvatar.into()),
+                ]
+                .spacing(10),
+            ]
+            .width(Fill)
+            .spacing(10)
+        ]
+        .spacing(10);
+
+        let mut page = Column::new().spacing(10);
+        for (field, content) in &self.fields {
+            let field = *field;
+            page = page.push(editor(
+                field.label().to_string(),
+                content,
+                Box::new(move |a| CharEditorCommand::Field(field, a).into()),
+            ));
+        }
+        for (greetings, label, add, contents) in [
+            (
+                Greetings::Alternate,
+                "Alternate greeting",
+                "Add alternate greeting",
+                &self.alternate_greetings,
+            ),
+            (
+                Greetings::GroupOnly,
+                "Group only greeting",
+                "Add group only greeting",
+                &self.group_greetings,
+            ),
+        ] {
+            for (idx, content) in contents.iter().enumerate() {
+                page = page.push(
+                    row![
+                            editor(
+                                format!("{label} {}", idx + 1),
+                                content,
+                                Box::new(
+                                    move |a| CharEditorCommand::Greeting(greetings, idx, a).into()
+                                ),
+                            )
+                            .width(Fill),
+                            button("Remove", settings)
+                                .on_press(CharEditorCommand::RemoveGreeting(greetings, idx).into()),
+                        ]
+                    .spacing(10),
+                );
+            }
+            page = page.push(
+                button(add, settings).on_press(CharEditorCommand::AddGreeting(greetings).into()),
+            );
+        }
+        page = page.push(editor(
+            "Tags, one per line".to_string(),
+            &self.tags,
+            Box::new(|a| CharEditorCommand::Tags(a).into()),
+        ));
+
+        let lorebook = iced::widget::button(text(
+            match self.show_lorebook {
+                true => "Back to the character".to_string(),
+                false => format!("Lorebook, {} entries", self.lorebook.len()),
+            },
+            settings,
+        ))
+        .on_press(CharEditorCommand::ToggleLorebook.into());
+        let body: Element<'a, AppCommand> = match self.show_lorebook {
+            true => self.lorebook.view(settings),
+            false => scrollable(page).height(Fill).into(),
+        };
+        let mut page = column![header, lorebook, body].spacing(10).padding(10);
+
+        let errors = self.errors();
+        for problem in errors.iter().chain(&self.warnings()) {
+            page = page.push(text(problem.clone(), settings));
+        }
+        let mut save = button("Save", settings);
+        if errors.is_empty() {
+            save = save.on_press(CharEditorCommand::Save.into());
+        }
+        page = page.push(
+            row![
+                save,
+                button("Cancel", settings).on_press(CharEditorCommand::Cancel.into())
+            ]
+            .spacing(10),
+        );
+
+        container(page).width(Fill).style(Self::box_style).into()
+    }
+
+    fn box_style(theme: &Theme) -> iced::widget::container::Style {
+        container::rounded_box(theme)
+            .background(colors::fill::SECONDARY_DARK)
+            .border(Border::default().rounded(12))
+    }
+}
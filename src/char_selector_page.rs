@@ -8,6 +8,7 @@ use iced_modern_theme::colors::colors;
 
 use crate::{
     AppCommand,
+    export::CardFormat,
     persona::{
         Persona,
         loader::{PersonaLoader, Subdir},
@@ -36,6 +37,27 @@ impl CharSelectorPage {
         p
     }
 
+    pub fn char(&self, idx: usize) -> &Persona {
+        &self.chars[idx]
+    }
+
+    /// Puts an edited char in place of the one loaded from the same path.
+    pub fn replace(&mut self, char: Persona) {
+        if let Some(old) = self.chars.iter_mut().find(|c| c.path() == char.path()) {
+            *old = char;
+        }
+    }
+
+    /// Names of every char but the one at `idx`.
+    pub fn other_names(&self, idx: usize) -> Vec<String> {
+        self.chars
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != idx)
+            .map(|(_, c)| c.name().to_string())
+            .collect()
+    }
+
     fn reorder(&mut self) {
         self.chars.sort_by_key(|p| p.modified_time());
         self.chars.reverse();
@@ -51,7 +73,14 @@ impl CharSelectorPage {
                         char.image().height(200),
                         column![
                             bold_text(char.name(), settings),
-                            button("Edit", settings),
+                            button("Edit", settings).on_press(AppCommand::EditChar(idx)),
+                            row![
+                                button("Export PNG", settings)
+                                    .on_press(AppCommand::ExportChar(idx, CardFormat::Png)),
+                                button("Export CharX", settings)
+                                    .on_press(AppCommand::ExportChar(idx, CardFormat::CharX)),
+                            ]
+                            .spacing(10),
                             button("Select", settings).on_press(AppCommand::SelectedChar(idx))
                         ]
                         .width(Fill)
@@ -1,34 +1,50 @@
-use chrono::Local;
+use anyhow::{Result, anyhow};
 use iced::{
     Alignment, Border, Element, Font,
     Length::{self, Fill},
-    Theme,
+    Task, Theme,
     font::Weight,
     widget::{
         TextEditor, column, container,
         keyed::Column,
-        rich_text, row, scrollable, span,
+        rich_text, row, scrollable,
+        scrollable::RelativeOffset,
+        span,
         text_editor::{Action, Content},
     },
 };
 use iced_modern_theme::colors::colors;
 use llm::chat::ChatMessage;
-use log::error;
+use log::{error, trace};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::Path,
+};
 
 use crate::{
     AppCommand,
     chat_page::MessageCommand,
     formater::Formater,
-    message::Message,
+    lore::report::Report,
+    message::{Message, OwnerType},
     persona::Persona,
     settings::Settings,
     utils::widgets::{button, text},
 };
 
-#[derive(Default)]
+#[derive(Default, Clone, Serialize, Deserialize)]
+#[serde(into = "SavedChat", try_from = "SavedChat")]
 pub struct Chat {
     childs: Vec<MessageNode>,
     selected: usize,
+    /// World books attached to this chat only.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    worlds: Vec<String>,
+    /// Folder of the user persona locked to this chat.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    user: Option<String>,
 }
 
 impl Chat {
@@ -42,9 +58,66 @@ impl Chat {
                 None => vec![],
             },
             selected: 0,
+            worlds: vec![],
+            user: None,
+        }
+    }
+
+    /// Reads a saved chat without reattaching the personas.
+    pub fn read(path: &Path) -> Result<Self> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn from_nodes(childs: Vec<MessageNode>, selected: usize) -> Self {
+        Chat {
+            childs,
+            selected,
+            worlds: vec![],
+            user: None,
         }
     }
 
+    pub fn load(path: &Path, char: &Persona, user: &Persona) -> Result<Self> {
+        let mut chat = Self::read(path)?;
+        chat.set_owners(char, user);
+        trace!("Loaded chat {}", path.display());
+        Ok(chat)
+    }
+
+    /// Points every message at the current char and user personas.
+    pub fn set_owners(&mut self, char: &Persona, user: &Persona) {
+        for child in &mut self.childs {
+            child.set_owners(char, user);
+        }
+    }
+
+    pub fn worlds(&self) -> &[String] {
+        &self.worlds
+    }
+
+    pub fn set_world(&mut self, world: String, attached: bool) {
+        self.worlds.retain(|w| *w != world);
+        if attached {
+            self.worlds.push(world)
+        }
+    }
+
+    pub fn user_lock(&self) -> Option<&str> {
+        self.user.as_deref()
+    }
+
+    pub fn lock_user(&mut self, folder: Option<String>) {
+        self.user = folder
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
     pub fn push(&mut self, message: Message) {
         match self.childs.is_empty() {
             true => self.childs.push(MessageNode::new(message)),
@@ -52,6 +125,17 @@ impl Chat {
         }
     }
 
+    /// Adds a level of sibling messages under the selected leaf and selects one of them.
+    pub fn push_alternatives(&mut self, messages: Vec<Message>, selected: usize) {
+        match self.childs.is_empty() {
+            true => {
+                self.childs = messages.into_iter().map(MessageNode::new).collect();
+                self.selected = selected.min(self.childs.len().saturating_sub(1));
+            }
+            false => self.childs[self.selected].push_alternatives(messages, selected),
+        }
+    }
+
     pub fn get_current_chat(&self) -> Vec<Message> {
         let mut chat = vec![];
         if !self.childs.is_empty() {
@@ -63,6 +147,7 @@ impl Chat {
     pub fn get_chat_messages(&self) -> Vec<ChatMessage> {
         self.get_current_chat()
             .iter()
+            .filter(|msg| !msg.excluded)
             .map(|msg| msg.to_chat_message())
             .collect()
     }
@@ -72,14 +157,10 @@ impl Chat {
         if !self.childs.is_empty() && idx > 0 {
             self.childs[self.selected].get_current_chat_until(&mut chat, idx - 1);
         }
-        chat.iter().map(|msg| msg.to_chat_message()).collect()
-    }
-
-    pub fn append_last_message(&mut self, text: &str) {
-        match self.childs.is_empty() {
-            true => error!("Error: Trying to append to non existing message"),
-            false => self.childs[self.selected].append_last_message(text),
-        }
+        chat.iter()
+            .filter(|msg| !msg.excluded)
+            .map(|msg| msg.to_chat_message())
+            .collect()
     }
 
     pub fn previous(&mut self, idx: usize) {
@@ -132,6 +213,19 @@ impl Chat {
         }
     }
 
+    pub fn save_edit(&mut self, idx: usize) {
+        match idx == 0 {
+            true => {
+                let message = &mut self.childs[self.selected].message;
+                match message.editing.take() {
+                    Some(content) => message.text = content.text().trim_end().to_string(),
+                    None => error!("Content not found"),
+                }
+            }
+            false => self.childs[self.selected].save_edit(idx - 1),
+        }
+    }
+
     pub fn abort_edit(&mut self, idx: usize) {
         match idx == 0 {
             true => self.childs[self.selected].message.editing = None,
@@ -149,6 +243,16 @@ impl Chat {
         }
     }
 
+    pub fn toggle_excluded(&mut self, idx: usize) {
+        match idx == 0 {
+            true => {
+                let message = &mut self.childs[self.selected].message;
+                message.excluded = !message.excluded
+            }
+            false => self.childs[self.selected].toggle_excluded(idx - 1),
+        }
+    }
+
     pub fn delete(&mut self, idx: usize) {
         match idx == 0 {
             true => {
@@ -161,8 +265,163 @@ impl Chat {
         }
     }
 
-    pub fn view<'a>(&'a self, settings: &'a Settings) -> Element<'a, AppCommand> {
-        scrollable(self.create_column_view(settings))
+    pub fn selected_path(&self) -> Vec<usize> {
+        let mut path = vec![];
+        if !self.childs.is_empty() {
+            path.push(self.selected);
+            self.childs[self.selected].selected_path(&mut path);
+        }
+        path
+    }
+
+    /// Adds `count` empty char siblings next to the message at `idx` and
+    /// returns their paths, the first one gets selected.
+    pub fn add_alternatives(
+        &mut self,
+        idx: usize,
+        count: usize,
+        char: &Persona,
+    ) -> Vec<Vec<usize>> {
+        let prefix: Vec<usize> = self.selected_path().into_iter().take(idx).collect();
+        let siblings = match self.node_mut(&prefix) {
+            Some(parent) => &mut parent.childs,
+            None => &mut self.childs,
+        };
+        let first = siblings.len();
+        for _ in 0..count {
+            siblings.push(MessageNode::new(Message::empty_from_char(char.clone())));
+        }
+        let paths: Vec<Vec<usize>> = (first..first + count)
+            .map(|i| {
+                let mut path = prefix.clone();
+                path.push(i);
+                path
+            })
+            .collect();
+        if let Some(path) = paths.first() {
+            self.select_path(path);
+        }
+        paths
+    }
+
+    pub fn append_to(&mut self, path: &[usize], text: &str) {
+        match self.node_mut(path) {
+            Some(node) => node.message.text.push_str(text),
+            None => error!("Error: Trying to append to non existing message"),
+        }
+    }
+
+    /// Keeps the lore report of the generation that wrote the message at `path`.
+    pub fn set_lore(&mut self, path: &[usize], report: Report) {
+        match self.node_mut(path) {
+            Some(node) => node.message.lore = Some(report),
+            None => error!("Error: Trying to attach lore to non existing message"),
+        }
+    }
+
+    pub fn toggle_lore(&mut self, idx: usize) {
+        let path = self.selected_path();
+        if let Some(node) = path.get(..=idx).and_then(|path| self.node_mut(path)) {
+            node.message.show_lore = !node.message.show_lore
+        }
+    }
+
+    fn node_mut(&mut self, path: &[usize]) -> Option<&mut MessageNode> {
+        let (&first, rest) = path.split_first()?;
+        self.childs.get_mut(first)?.node_mut(rest)
+    }
+
+    /// Each level of the selected path as its sibling messages and the selected index.
+    pub fn selected_levels(&self) -> Vec<(Vec<&Message>, usize)> {
+        let mut levels = vec![];
+        let mut childs = &self.childs;
+        let mut selected = self.selected;
+        while !childs.is_empty() {
+            levels.push((childs.iter().map(|c| &c.message).collect(), selected));
+            let node = &childs[selected];
+            childs = &node.childs;
+            selected = node.selected;
+        }
+        levels
+    }
+
+    /// Copies the selected path up to `idx` into a new linear chat.
+    pub fn fork(&self, idx: usize) -> Chat {
+        let mut chat = Chat {
+            worlds: self.worlds.clone(),
+            user: self.user.clone(),
+            ..Chat::default()
+        };
+        for message in self.get_current_chat().into_iter().take(idx + 1) {
+            chat.push(message);
+        }
+        chat
+    }
+
+    /// Selects every node along `path`, a list of child indices from the root.
+    pub fn select_path(&mut self, path: &[usize]) {
+        if let Some((&first, rest)) = path.split_first()
+            && first < self.childs.len()
+        {
+            self.selected = first;
+            self.childs[first].select_path(rest);
+        }
+    }
+
+    /// Paths of the messages containing `query`, on the selected branch only
+    /// or across every branch of the tree.
+    pub fn find(&self, query: &str, all_branches: bool) -> Vec<Vec<usize>> {
+        let query = query.to_lowercase();
+        let matches = |message: &Message| message.text.to_lowercase().contains(&query);
+        match all_branches {
+            true => self
+                .tree_items(&HashSet::new())
+                .into_iter()
+                .filter(|item| matches(item.message))
+                .map(|item| item.path)
+                .collect(),
+            false => {
+                let path = self.selected_path();
+                self.get_current_chat()
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, message)| matches(message))
+                    .map(|(idx, _)| path[..=idx].to_vec())
+                    .collect()
+            }
+        }
+    }
+
+    /// Flattens the whole tree depth first, skipping the childs of collapsed nodes.
+    pub fn tree_items(&self, collapsed: &HashSet<Vec<usize>>) -> Vec<TreeItem<'_>> {
+        let mut items = vec![];
+        for (idx, child) in self.childs.iter().enumerate() {
+            child.tree_items(&mut items, vec![idx], idx == self.selected, collapsed);
+        }
+        items
+    }
+
+    /// Scrolls the chat view so the message at `idx` is roughly in view.
+    pub fn scroll_to(&self, idx: usize) -> Task<AppCommand> {
+        let len = self.get_current_chat().len();
+        let y = match len > 1 {
+            true => 1.0 - idx.min(len - 1) as f32 / (len - 1) as f32,
+            false => 0.0,
+        };
+        scrollable::snap_to(Self::scrollable_id(), RelativeOffset { x: 0.0, y })
+    }
+
+    fn scrollable_id() -> scrollable::Id {
+        scrollable::Id::new("chat")
+    }
+
+    pub fn view<'a>(
+        &'a self,
+        highlight: Option<&'a str>,
+        settings: &'a Settings,
+    ) -> Element<'a, AppCommand> {
+        scrollable(self.create_column_view(highlight, settings))
+            .id(Self::scrollable_id())
             .anchor_bottom()
             .height(Fill)
             .width(Fill)
@@ -170,7 +429,11 @@ impl Chat {
             .into()
     }
 
-    fn create_column_view<'a>(&'a self, settings: &'a Settings) -> Column<'a, usize, AppCommand> {
+    fn create_column_view<'a>(
+        &'a self,
+        highlight: Option<&'a str>,
+        settings: &'a Settings,
+    ) -> Column<'a, usize, AppCommand> {
         let mut keyed_column = Column::new().spacing(10);
         let mut nb_childs = self.childs.len();
         let mut selected = self.selected;
@@ -182,57 +445,14 @@ impl Chat {
         loop {
             keyed_column = keyed_column.push(
                 idx,
-                container(
-                    row![
-                        // image(current_node.message.get_avatar_uri())
-                        current_node.message.owner.image().width(Fill),
-                        column![
-                            row![
-                                rich_text![
-                                    span(current_node.message.owner.name())
-                                        .font(Font {
-                                            weight: Weight::Bold,
-                                            ..Font::default()
-                                        })
-                                        .size(settings.font_size()),
-                                    "  ",
-                                    span(Local::now().format("%B %d, %Y %H:%M").to_string())
-                                        .size(settings.font_size())
-                                ]
-                                .width(Fill),
-                                text(format!("{}/{}", selected + 1, nb_childs), settings),
-                                button("<", settings)
-                                    .on_press(MessageCommand::Previous(idx).into()),
-                                button(">", settings).on_press(MessageCommand::Next(idx).into()),
-                                button("E", settings)
-                                    .on_press(MessageCommand::ToggleEdit(idx).into()),
-                                button("A", settings)
-                                    .on_press(MessageCommand::AbortEdit(idx).into()),
-                                button("D", settings).on_press(MessageCommand::Delete(idx).into())
-                            ]
-                            .align_y(Alignment::Center)
-                            .spacing(2),
-                            if let Some(edit) = &current_node.message.editing {
-                                let idx2 = idx;
-                                Element::from(
-                                    TextEditor::new(edit).size(settings.font_size()).on_action(
-                                        move |a| MessageCommand::EditAction(idx2, a).into(),
-                                    ),
-                                )
-                            } else {
-                                Element::from(Formater::rich_text(
-                                    &current_node.message.text,
-                                    settings,
-                                ))
-                            },
-                        ]
-                        .spacing(4)
-                        .width(Length::FillPortion(6)),
-                    ]
-                    .padding(10)
-                    .spacing(10),
-                )
-                .style(Self::message_style),
+                Self::message_view(
+                    &current_node.message,
+                    idx,
+                    selected,
+                    nb_childs,
+                    highlight,
+                    settings,
+                ),
             );
             idx += 1;
             if current_node.childs.is_empty() {
@@ -244,19 +464,248 @@ impl Chat {
         }
     }
 
+    fn message_view<'a>(
+        message: &'a Message,
+        idx: usize,
+        selected: usize,
+        nb_childs: usize,
+        highlight: Option<&'a str>,
+        settings: &'a Settings,
+    ) -> Element<'a, AppCommand> {
+        let header = row![
+            rich_text![
+                span(message.owner.name())
+                    .font(Font {
+                        weight: Weight::Bold,
+                        ..Font::default()
+                    })
+                    .size(settings.font_size()),
+                "  ",
+                span(message.time.format("%B %d, %Y %H:%M").to_string()).size(settings.font_size())
+            ]
+            .width(Fill),
+            text(format!("{}/{}", selected + 1, nb_childs), settings),
+            button("<", settings).on_press(MessageCommand::Previous(idx).into()),
+            button(">", settings).on_press(MessageCommand::Next(idx).into()),
+            button("N", settings).on_press(MessageCommand::GenerateAlternatives(idx).into()),
+            button("E", settings).on_press(MessageCommand::ToggleEdit(idx).into()),
+            button("S", settings).on_press_maybe(
+                message
+                    .editing
+                    .as_ref()
+                    .map(|_| MessageCommand::SaveEdit(idx).into())
+            ),
+            button("A", settings).on_press(MessageCommand::AbortEdit(idx).into()),
+            button("H", settings).on_press(MessageCommand::ToggleExcluded(idx).into()),
+            button("L", settings).on_press_maybe(
+                message
+                    .lore
+                    .as_ref()
+                    .map(|_| MessageCommand::ToggleLore(idx).into())
+            ),
+            button("F", settings).on_press(MessageCommand::Fork(idx).into()),
+            button("D", settings).on_press(MessageCommand::Delete(idx).into())
+        ]
+        .align_y(Alignment::Center)
+        .spacing(2);
+
+        let body = if let Some(edit) = &message.editing {
+            Element::from(
+                TextEditor::new(edit)
+                    .size(settings.font_size())
+                    .on_action(move |a| MessageCommand::EditAction(idx, a).into()),
+            )
+        } else {
+            Formater::rich_text(&message.text, message.excluded, highlight, settings)
+        };
+        let body = match (&message.lore, message.show_lore) {
+            (Some(report), true) => column![body, report.view(settings)].spacing(10).into(),
+            _ => body,
+        };
+
+        match message.owner_type {
+            OwnerType::User | OwnerType::Char => container(
+                row![
+                    message.owner.image().width(Fill),
+                    column![header, body]
+                        .spacing(4)
+                        .width(Length::FillPortion(6)),
+                ]
+                .padding(10)
+                .spacing(10),
+            )
+            .style(if message.excluded {
+                Self::excluded_style
+            } else {
+                Self::message_style
+            })
+            .into(),
+            OwnerType::System => container(
+                column![header, body]
+                    .align_x(Alignment::Center)
+                    .spacing(4)
+                    .padding(10),
+            )
+            .style(Self::system_style)
+            .into(),
+            OwnerType::Narrator => container(column![header, body].spacing(4).padding(10))
+                .style(Self::narrator_style)
+                .into(),
+        }
+    }
+
     fn message_style(theme: &Theme) -> iced::widget::container::Style {
         container::rounded_box(theme)
             .background(colors::fill::SECONDARY_DARK)
             .border(Border::default().rounded(12))
     }
+
+    fn excluded_style(theme: &Theme) -> iced::widget::container::Style {
+        container::rounded_box(theme)
+            .background(colors::fill::BACKGROUND_DARK)
+            .border(Border::default().rounded(12))
+    }
+
+    fn system_style(theme: &Theme) -> iced::widget::container::Style {
+        container::rounded_box(theme)
+            .background(colors::fill::BACKGROUND_DARK)
+            .border(
+                Border::default()
+                    .rounded(12)
+                    .width(1)
+                    .color(colors::state::SEPARATOR_DARK),
+            )
+    }
+
+    fn narrator_style(theme: &Theme) -> iced::widget::container::Style {
+        container::rounded_box(theme)
+            .background(colors::fill::TERTIARY_DARK)
+            .border(Border::default().rounded(12))
+    }
+}
+
+pub struct TreeItem<'a> {
+    pub message: &'a Message,
+    pub path: Vec<usize>,
+    pub active: bool,
+    pub nb_childs: usize,
+    pub collapsed: bool,
 }
 
-struct MessageNode {
+#[derive(Clone, Deserialize)]
+pub struct MessageNode {
     message: Message,
     childs: Vec<MessageNode>,
     selected: usize,
 }
 
+/// How a chat is saved: every message once, pointing at its parent, so long
+/// chats don't nest past the depth serde_json reads back.
+#[derive(Serialize, Deserialize)]
+struct SavedChat {
+    /// Parents always come before their children.
+    #[serde(default)]
+    nodes: Vec<SavedNode>,
+    /// The nested tree of chats saved before the flat format.
+    #[serde(default, skip_serializing)]
+    childs: Vec<MessageNode>,
+    selected: usize,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    worlds: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    user: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedNode {
+    id: usize,
+    parent: Option<usize>,
+    /// Index of the selected child.
+    #[serde(default)]
+    selected: usize,
+    message: Message,
+}
+
+impl From<Chat> for SavedChat {
+    fn from(chat: Chat) -> Self {
+        let mut nodes = vec![];
+        let mut stack: Vec<(Option<usize>, MessageNode)> =
+            chat.childs.into_iter().rev().map(|n| (None, n)).collect();
+        while let Some((parent, node)) = stack.pop() {
+            let id = nodes.len();
+            stack.extend(node.childs.into_iter().rev().map(|c| (Some(id), c)));
+            nodes.push(SavedNode {
+                id,
+                parent,
+                selected: node.selected,
+                message: node.message,
+            });
+        }
+        SavedChat {
+            nodes,
+            childs: vec![],
+            selected: chat.selected,
+            worlds: chat.worlds,
+            user: chat.user,
+        }
+    }
+}
+
+impl TryFrom<SavedChat> for Chat {
+    type Error = anyhow::Error;
+
+    fn try_from(saved: SavedChat) -> Result<Self> {
+        let mut chat = Chat {
+            childs: saved.childs,
+            selected: saved.selected,
+            worlds: saved.worlds,
+            user: saved.user,
+        };
+        let nodes = saved.nodes;
+        let mut positions: HashMap<usize, usize> = HashMap::new();
+        let mut children: Vec<Vec<usize>> = vec![vec![]; nodes.len()];
+        let mut roots = vec![];
+        for (pos, node) in nodes.iter().enumerate() {
+            match node.parent {
+                None => roots.push(pos),
+                Some(parent) => match positions.get(&parent) {
+                    Some(&parent) => children[parent].push(pos),
+                    None => {
+                        return Err(anyhow!(
+                            "Message {} comes before its parent {parent}",
+                            node.id
+                        ));
+                    }
+                },
+            }
+            if positions.insert(node.id, pos).is_some() {
+                return Err(anyhow!("Message id {} is used twice", node.id));
+            }
+        }
+        // Children come after their parent, so building from the end finds them ready.
+        let mut built: Vec<Option<MessageNode>> = (0..nodes.len()).map(|_| None).collect();
+        for (pos, node) in nodes.into_iter().enumerate().rev() {
+            let childs: Vec<MessageNode> = children[pos]
+                .iter()
+                .filter_map(|child| built[*child].take())
+                .collect();
+            built[pos] = Some(MessageNode {
+                selected: node.selected.min(childs.len().saturating_sub(1)),
+                message: node.message,
+                childs,
+            });
+        }
+        if !roots.is_empty() {
+            chat.childs = roots
+                .iter()
+                .filter_map(|root| built[*root].take())
+                .collect();
+        }
+        chat.selected = chat.selected.min(chat.childs.len().saturating_sub(1));
+        Ok(chat)
+    }
+}
+
 impl MessageNode {
     fn new(message: Message) -> Self {
         MessageNode {
@@ -266,6 +715,14 @@ impl MessageNode {
         }
     }
 
+    pub fn with_childs(message: Message, childs: Vec<MessageNode>, selected: usize) -> Self {
+        MessageNode {
+            message,
+            childs,
+            selected,
+        }
+    }
+
     fn push(&mut self, message: Message) {
         match self.childs.is_empty() {
             true => self.childs.push(MessageNode::new(message)),
@@ -273,6 +730,71 @@ impl MessageNode {
         }
     }
 
+    fn set_owners(&mut self, char: &Persona, user: &Persona) {
+        self.message.set_owner(char, user);
+        for child in &mut self.childs {
+            child.set_owners(char, user);
+        }
+    }
+
+    fn selected_path(&self, path: &mut Vec<usize>) {
+        if !self.childs.is_empty() {
+            path.push(self.selected);
+            self.childs[self.selected].selected_path(path);
+        }
+    }
+
+    fn node_mut(&mut self, path: &[usize]) -> Option<&mut MessageNode> {
+        match path.split_first() {
+            Some((&first, rest)) => self.childs.get_mut(first)?.node_mut(rest),
+            None => Some(self),
+        }
+    }
+
+    fn select_path(&mut self, path: &[usize]) {
+        if let Some((&first, rest)) = path.split_first()
+            && first < self.childs.len()
+        {
+            self.selected = first;
+            self.childs[first].select_path(rest);
+        }
+    }
+
+    fn tree_items<'a>(
+        &'a self,
+        items: &mut Vec<TreeItem<'a>>,
+        path: Vec<usize>,
+        active: bool,
+        collapsed: &HashSet<Vec<usize>>,
+    ) {
+        let is_collapsed = collapsed.contains(&path);
+        items.push(TreeItem {
+            message: &self.message,
+            path: path.clone(),
+            active,
+            nb_childs: self.childs.len(),
+            collapsed: is_collapsed,
+        });
+        if is_collapsed {
+            return;
+        }
+        for (idx, child) in self.childs.iter().enumerate() {
+            let mut child_path = path.clone();
+            child_path.push(idx);
+            child.tree_items(items, child_path, active && idx == self.selected, collapsed);
+        }
+    }
+
+    fn push_alternatives(&mut self, messages: Vec<Message>, selected: usize) {
+        match self.childs.is_empty() {
+            true => {
+                self.childs = messages.into_iter().map(MessageNode::new).collect();
+                self.selected = selected.min(self.childs.len().saturating_sub(1));
+            }
+            false => self.childs[self.selected].push_alternatives(messages, selected),
+        }
+    }
+
     fn get_current_chat(&self, chat: &mut Vec<Message>) {
         chat.push(self.message.clone());
         if !self.childs.is_empty() {
@@ -287,13 +809,6 @@ impl MessageNode {
         }
     }
 
-    fn append_last_message(&mut self, text: &str) {
-        match self.childs.is_empty() {
-            true => self.message.text.push_str(text),
-            false => self.childs[self.selected].append_last_message(text),
-        }
-    }
-
     fn previous(&mut self, idx: usize) {
         match idx == 0 {
             true => {
@@ -344,6 +859,19 @@ impl MessageNode {
         }
     }
 
+    pub fn save_edit(&mut self, idx: usize) {
+        match idx == 0 {
+            true => {
+                let message = &mut self.childs[self.selected].message;
+                match message.editing.take() {
+                    Some(content) => message.text = content.text().trim_end().to_string(),
+                    None => error!("Content not found"),
+                }
+            }
+            false => self.childs[self.selected].save_edit(idx - 1),
+        }
+    }
+
     pub fn abort_edit(&mut self, idx: usize) {
         match idx == 0 {
             true => self.childs[self.selected].message.editing = None,
@@ -361,6 +889,16 @@ impl MessageNode {
         }
     }
 
+    pub fn toggle_excluded(&mut self, idx: usize) {
+        match idx == 0 {
+            true => {
+                let message = &mut self.childs[self.selected].message;
+                message.excluded = !message.excluded
+            }
+            false => self.childs[self.selected].toggle_excluded(idx - 1),
+        }
+    }
+
     pub fn delete(&mut self, idx: usize) {
         match idx == 0 {
             true => {
@@ -373,3 +911,57 @@ impl MessageNode {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::Chat;
+    use crate::{message::Message, persona::Persona};
+
+    fn texts(chat: &Chat) -> Vec<String> {
+        chat.get_current_chat()
+            .into_iter()
+            .map(|m| m.text)
+            .collect()
+    }
+
+    #[test]
+    fn long_chats_reload() {
+        let (char, user) = (Persona::default_char(), Persona::default_user());
+        let mut chat = Chat::default();
+        for i in 0..500 {
+            match i % 2 {
+                0 => chat.push(Message::from_user(user.clone(), format!("Message {i}"))),
+                _ => chat.push(Message::from_char(char.clone(), format!("Message {i}"))),
+            }
+        }
+        chat.add_alternatives(250, 2, &char);
+        chat.append_to(&chat.selected_path(), "Alternative");
+
+        let path = std::env::temp_dir().join("fullmoon-chat-tests/long.json");
+        let _ = fs::remove_file(&path);
+        chat.save(&path).unwrap();
+        let loaded = Chat::load(&path, &char, &user).unwrap();
+        assert_eq!(loaded.selected_path(), chat.selected_path());
+        assert_eq!(texts(&loaded), texts(&chat));
+        assert_eq!(texts(&loaded).len(), 251);
+        assert_eq!(
+            serde_json::to_value(&loaded).unwrap(),
+            serde_json::to_value(&chat).unwrap()
+        );
+    }
+
+    #[test]
+    fn nested_chats_still_load() {
+        let chat: Chat = serde_json::from_str(
+            r#"{"selected": 0, "childs": [{"selected": 1, "message":
+                {"owner_type": "Char", "text": "Hi"}, "childs": [
+                    {"selected": 0, "childs": [], "message": {"owner_type": "User", "text": "A"}},
+                    {"selected": 0, "childs": [], "message": {"owner_type": "User", "text": "B"}}
+                ]}]}"#,
+        )
+        .unwrap();
+        assert_eq!(texts(&chat), ["Hi", "B"]);
+    }
+}
@@ -0,0 +1,96 @@
+use iced::{
+    Alignment, Element,
+    widget::{checkbox, row, text_input},
+};
+
+use crate::{
+    AppCommand,
+    chat_page::{ChatCommand, chat::Chat},
+    settings::Settings,
+    utils::widgets::{button, text},
+};
+
+/// Find bar state for searching inside the open chat.
+#[derive(Default)]
+pub struct Find {
+    query: String,
+    all_branches: bool,
+    hits: Vec<Vec<usize>>,
+    current: usize,
+}
+
+impl Find {
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    pub fn set_query(&mut self, query: String, chat: &Chat) {
+        self.query = query;
+        self.refresh(chat);
+    }
+
+    pub fn set_all_branches(&mut self, all_branches: bool, chat: &Chat) {
+        self.all_branches = all_branches;
+        self.refresh(chat);
+    }
+
+    fn refresh(&mut self, chat: &Chat) {
+        self.hits = match self.query.is_empty() {
+            true => vec![],
+            false => chat.find(&self.query, self.all_branches),
+        };
+        self.current = 0;
+    }
+
+    /// Moves to the next hit and returns its path.
+    pub fn next(&mut self) -> Option<Vec<usize>> {
+        if self.hits.is_empty() {
+            return None;
+        }
+        self.current = (self.current + 1) % self.hits.len();
+        self.hits.get(self.current).cloned()
+    }
+
+    /// Moves to the previous hit and returns its path.
+    pub fn previous(&mut self) -> Option<Vec<usize>> {
+        if self.hits.is_empty() {
+            return None;
+        }
+        self.current = (self.current + self.hits.len() - 1) % self.hits.len();
+        self.hits.get(self.current).cloned()
+    }
+
+    pub fn current(&self) -> Option<Vec<usize>> {
+        self.hits.get(self.current).cloned()
+    }
+
+    pub fn view<'a>(&'a self, settings: &'a Settings) -> Element<'a, AppCommand> {
+        row![
+            text_input("Find in chat", &self.query)
+                .id(Self::input_id())
+                .size(settings.font_size())
+                .on_input(|q| ChatCommand::FindQuery(q).into())
+                .on_submit(ChatCommand::FindNext.into()),
+            text(
+                match self.hits.is_empty() {
+                    true => "0/0".to_string(),
+                    false => format!("{}/{}", self.current + 1, self.hits.len()),
+                },
+                settings
+            ),
+            button("<", settings).on_press(ChatCommand::FindPrevious.into()),
+            button(">", settings).on_press(ChatCommand::FindNext.into()),
+            checkbox("All branches", self.all_branches)
+                .size(settings.font_size())
+                .on_toggle(|a| ChatCommand::FindAllBranches(a).into()),
+            button("X", settings).on_press(ChatCommand::ToggleFind.into()),
+        ]
+        .align_y(Alignment::Center)
+        .spacing(10)
+        .into()
+    }
+
+    pub fn input_id() -> text_input::Id {
+        text_input::Id::new("find")
+    }
+}
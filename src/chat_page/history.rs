@@ -0,0 +1,186 @@
+use std::collections::VecDeque;
+
+/// Number of undo steps kept before the oldest ones are dropped.
+const HISTORY_SIZE: usize = 100;
+
+/// Undo/redo stacks of full snapshots, taken before each tree mutation.
+pub struct History<T> {
+    undo: VecDeque<T>,
+    redo: Vec<T>,
+    capacity: usize,
+}
+
+impl<T> Default for History<T> {
+    fn default() -> Self {
+        Self::with_capacity(HISTORY_SIZE)
+    }
+}
+
+impl<T> History<T> {
+    pub fn with_capacity(capacity: usize) -> Self {
+        History {
+            undo: VecDeque::new(),
+            redo: vec![],
+            capacity,
+        }
+    }
+
+    pub fn record(&mut self, state: T) {
+        self.redo.clear();
+        self.undo.push_back(state);
+        while self.undo.len() > self.capacity {
+            self.undo.pop_front();
+        }
+    }
+
+    pub fn undo(&mut self, current: T) -> Option<T> {
+        let previous = self.undo.pop_back()?;
+        self.redo.push(current);
+        Some(previous)
+    }
+
+    pub fn redo(&mut self, current: T) -> Option<T> {
+        let next = self.redo.pop()?;
+        self.undo.push_back(current);
+        Some(next)
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use iced::widget::text_editor::{Action, Edit};
+    use serde_json::Value;
+
+    use super::History;
+    use crate::{chat_page::chat::Chat, message::Message, persona::Persona};
+
+    fn snapshot(chat: &Chat) -> Value {
+        serde_json::to_value(chat).unwrap()
+    }
+
+    fn sample_chat() -> Chat {
+        let char = Persona::default_char();
+        let user = Persona::default_user();
+        let mut chat = Chat::default();
+        chat.push(Message::from_char(char.clone(), "Hello".to_string()));
+        chat.push(Message::from_user(user.clone(), "Hi".to_string()));
+        chat.push(Message::from_char(char.clone(), "How are you?".to_string()));
+        chat.push(Message::from_user(user, "Fine".to_string()));
+        chat
+    }
+
+    fn rewrite(chat: &mut Chat, idx: usize, text: &str) {
+        chat.toggle_edit(idx);
+        chat.perform_action(idx, Action::SelectAll);
+        chat.perform_action(idx, Action::Edit(Edit::Paste(Arc::new(text.to_string()))));
+    }
+
+    #[test]
+    fn undo_and_redo_follow_recorded_states() {
+        let mut history = History::default();
+        history.record(1);
+        history.record(2);
+        assert_eq!(history.undo(3), Some(2));
+        assert_eq!(history.undo(2), Some(1));
+        assert_eq!(history.undo(1), None);
+        assert_eq!(history.redo(1), Some(2));
+        assert_eq!(history.redo(2), Some(3));
+        assert_eq!(history.redo(3), None);
+    }
+
+    #[test]
+    fn recording_clears_redo() {
+        let mut history = History::default();
+        history.record(1);
+        assert_eq!(history.undo(2), Some(1));
+        history.record(1);
+        assert_eq!(history.redo(4), None);
+    }
+
+    #[test]
+    fn history_is_bounded() {
+        let mut history = History::with_capacity(2);
+        for state in 0..5 {
+            history.record(state);
+        }
+        assert_eq!(history.undo(5), Some(4));
+        assert_eq!(history.undo(4), Some(3));
+        assert_eq!(history.undo(3), None);
+    }
+
+    #[test]
+    fn undo_restores_deleted_subtree() {
+        let mut chat = sample_chat();
+        let before = snapshot(&chat);
+        let mut history = History::default();
+
+        history.record(chat.clone());
+        chat.delete(1);
+        assert_eq!(chat.get_current_chat().len(), 1);
+
+        chat = history.undo(chat).unwrap();
+        assert_eq!(snapshot(&chat), before);
+
+        chat = history.redo(chat).unwrap();
+        assert_eq!(chat.get_current_chat().len(), 1);
+    }
+
+    #[test]
+    fn undo_restores_edit_branch_and_selection() {
+        let mut chat = sample_chat();
+        let before = snapshot(&chat);
+        let mut history = History::default();
+
+        rewrite(&mut chat, 2, "Changed");
+        history.record(chat.clone());
+        assert!(chat.toggle_edit(2));
+        assert_ne!(snapshot(&chat), before);
+
+        chat = history.undo(chat).unwrap();
+        assert_eq!(snapshot(&chat), before);
+    }
+
+    #[test]
+    fn undo_restores_swipe_and_push() {
+        let mut chat = sample_chat();
+        let before = snapshot(&chat);
+        let mut history = History::default();
+
+        history.record(chat.clone());
+        chat.next(3, Persona::default_char());
+        history.record(chat.clone());
+        chat.push(Message::from_user(Persona::default_user(), "More".into()));
+        let after = snapshot(&chat);
+
+        chat = history.undo(chat).unwrap();
+        chat = history.undo(chat).unwrap();
+        assert_eq!(snapshot(&chat), before);
+
+        chat = history.redo(chat).unwrap();
+        chat = history.redo(chat).unwrap();
+        assert_eq!(snapshot(&chat), after);
+    }
+
+    #[test]
+    fn undo_restores_in_place_edit() {
+        let mut chat = sample_chat();
+        let before = snapshot(&chat);
+        let mut history = History::default();
+
+        rewrite(&mut chat, 1, "Changed");
+        history.record(chat.clone());
+        chat.save_edit(1);
+        assert_eq!(chat.get_current_chat()[1].text, "Changed");
+        assert_eq!(chat.get_current_chat().len(), 4);
+
+        chat = history.undo(chat).unwrap();
+        assert_eq!(snapshot(&chat), before);
+    }
+}
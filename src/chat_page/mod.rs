@@ -1,32 +1,62 @@
 use iced::{
-    Alignment, Element, Task,
+    Alignment, Element, Length, Task, task,
     widget::{
-        TextEditor, row,
+        Column, Row, TextEditor, checkbox, container, row,
         text_editor::{Action, Content},
+        text_input,
     },
 };
 use llm::chat::ChatMessage;
+use log::{error, trace, warn};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
 
 use crate::{
     AppCommand,
-    chat_page::chat::Chat,
+    chat_page::{chat::Chat, find::Find, history::History, session::SessionLoader, tree::TreeView},
+    export::{ExportFormat, Exporter},
+    lore::{self, report::Report, world::WorldLoader},
     message::Message,
     persona::{
         Persona,
+        card::CharacterBook,
         loader::{PersonaLoader, Subdir},
     },
     settings::Settings,
-    utils::widgets::{bold_text, button},
+    utils::widgets::{bold_text, button, text},
 };
 
-mod chat;
+pub mod chat;
+mod find;
+mod history;
+pub mod session;
+mod tree;
 
 #[derive(Debug, Clone)]
 pub enum ChatCommand {
     InputChange(Action),
     InputSubmit,
     GenerateNextMessage,
-    StreamOk(String),
+    /// A chunk for the message at the given path, which stays put while
+    /// streams run as they are aborted before messages are removed.
+    StreamToOk(Vec<usize>, String),
+    /// The stream with the given id is done.
+    StreamEnd(usize),
+    Undo,
+    Redo,
+    ToggleTree,
+    SelectPath(Vec<usize>),
+    ToggleCollapse(Vec<usize>),
+    ToggleFind,
+    FindQuery(String),
+    FindAllBranches(bool),
+    FindNext,
+    FindPrevious,
+    ToggleExport,
+    ExportFullTree(bool),
+    Export(ExportFormat),
     MessageCommand(MessageCommand),
 }
 
@@ -40,9 +70,19 @@ impl From<ChatCommand> for crate::AppCommand {
 pub enum MessageCommand {
     Next(usize),
     Previous(usize),
+    /// Generates several alternatives to this message at once.
+    GenerateAlternatives(usize),
+    /// Starts editing, or branches off the edited text and regenerates.
     ToggleEdit(usize),
+    /// Rewrites the edited message in place, keeping its replies.
+    SaveEdit(usize),
     AbortEdit(usize),
     EditAction(usize, Action),
+    ToggleExcluded(usize),
+    /// Shows or hides how the lore of a generated message was picked.
+    ToggleLore(usize),
+    /// Branches the selected path up to this message into a new chat session.
+    Fork(usize),
     Delete(usize),
 }
 
@@ -54,36 +94,85 @@ impl From<MessageCommand> for crate::AppCommand {
 
 pub struct ChatPage {
     chat: Chat,
+    history: History<Chat>,
+    tree: Option<TreeView>,
+    find: Option<Find>,
+    /// Set while the export bar is shown, holds the "full tree" choice.
+    export: Option<bool>,
+    /// Where the last export was written, shown in the export bar.
+    exported: Option<PathBuf>,
+    session: PathBuf,
     input_message: Content,
     char: Persona,
+    /// The user persona in use, `selected_user` unless one is locked.
     user: Persona,
+    /// The user persona chosen on the user page.
+    selected_user: Persona,
+    user_lock: Option<UserLock>,
+    /// The replies being streamed by id, aborted before their message can go away.
+    streams: HashMap<usize, task::Handle>,
+    next_stream: usize,
+}
+
+/// Where the user persona in use is locked.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum UserLock {
+    Char,
+    Chat,
 }
 
 impl Default for ChatPage {
     fn default() -> Self {
+        let char = Persona::default_char();
         ChatPage {
             input_message: Content::new(),
             chat: Chat::default(),
-            char: Persona::default_char(),
+            history: History::default(),
+            tree: None,
+            find: None,
+            export: None,
+            exported: None,
+            session: SessionLoader::new_session_path(&char),
+            char,
             user: Persona::default_user(),
+            selected_user: Persona::default_user(),
+            user_lock: None,
+            streams: HashMap::new(),
+            next_stream: 0,
         }
     }
 }
 
 impl ChatPage {
     pub fn new(char: Persona, user: Persona) -> Self {
-        ChatPage {
+        let mut chat_page = ChatPage {
             input_message: Content::new(),
-            chat: Chat::with_messages(&char, &user),
+            chat: Chat::default(),
+            history: History::default(),
+            tree: None,
+            find: None,
+            export: None,
+            exported: None,
+            session: SessionLoader::new_session_path(&char),
             char,
-            user,
-        }
+            user: user.clone(),
+            selected_user: user,
+            user_lock: None,
+            streams: HashMap::new(),
+            next_stream: 0,
+        };
+        chat_page.new_chat();
+        chat_page
     }
 
-    pub fn try_load() -> Self {
+    pub fn try_load(settings: &Settings) -> Self {
         let char = PersonaLoader::load_most_recent_from_cache(Subdir::Chars);
-        let user = PersonaLoader::load_most_recent_from_cache(Subdir::Users);
-        ChatPage::new(char, user)
+        let user = PersonaLoader::load_selected_user(settings.user());
+        let mut chat_page = ChatPage::new(char, user);
+        if let Ok(session) = SessionLoader::most_recent_session(&chat_page.char) {
+            chat_page.open_session(session);
+        }
+        chat_page
     }
 
     pub fn set_char(&mut self, char: Persona) {
@@ -92,29 +181,195 @@ impl ChatPage {
     }
 
     pub fn new_chat(&mut self) {
+        self.abort_streams();
+        (self.user, self.user_lock) = self.locked_user(None);
         self.chat = Chat::with_messages(&self.char, &self.user);
+        self.history.clear();
+        if let Some(tree) = &mut self.tree {
+            tree.clear();
+        }
+        self.session = SessionLoader::new_session_path(&self.char);
+    }
+
+    pub fn selected_user(&self) -> &Persona {
+        &self.selected_user
+    }
+
+    /// Makes `user` speak for the user in the chats without a locked persona.
+    pub fn set_user(&mut self, user: Persona) {
+        self.selected_user = user;
+        self.refresh_user();
+    }
+
+    /// Swaps in an edited user persona, saved from `old_path`.
+    pub fn replace_user(&mut self, old_path: &Path, user: Persona) {
+        if self.selected_user.path() == old_path {
+            self.selected_user = user;
+        }
+        self.refresh_user();
+    }
+
+    pub fn user_lock(&self) -> Option<&str> {
+        self.chat.user_lock()
+    }
+
+    /// Locks the user persona in `folder` to the current chat, or unlocks it.
+    pub fn lock_user(&mut self, folder: Option<String>) {
+        self.chat.lock_user(folder);
+        self.refresh_user();
+        self.save();
+    }
+
+    /// Picks the user persona again, after a lock or a persona changed.
+    pub fn refresh_user(&mut self) {
+        (self.user, self.user_lock) = self.locked_user(self.chat.user_lock());
+        self.chat.set_owners(&self.char, &self.user);
+    }
+
+    /// The user persona locked to the chat, else to the char, else the selected one.
+    fn locked_user(&self, chat_lock: Option<&str>) -> (Persona, Option<UserLock>) {
+        let lock = match (chat_lock, self.char.user_lock()) {
+            (Some(folder), _) => Some((folder.to_string(), UserLock::Chat)),
+            (None, Some(folder)) => Some((folder, UserLock::Char)),
+            (None, None) => None,
+        };
+        if let Some((folder, lock)) = lock {
+            match PersonaLoader::load_user(&folder) {
+                Ok(user) => return (user, Some(lock)),
+                Err(e) => warn!("Locked user persona {folder}: {e}"),
+            }
+        }
+        (self.selected_user.clone(), None)
+    }
+
+    /// Swaps in an edited version of the current char, following its chats
+    /// when it was renamed.
+    pub fn replace_char(&mut self, char: Persona) {
+        if self.char.path() != char.path() {
+            return;
+        }
+        if self.char.name() != char.name()
+            && let Some(file) = self.session.file_name()
+        {
+            self.session = SessionLoader::chats_path(char.name()).join(file);
+        }
+        self.char = char;
+        self.refresh_user();
+    }
+
+    /// Opens a saved session of `char` with the message at `path` selected and in view.
+    pub fn open_at(&mut self, char: Persona, session: PathBuf, path: &[usize]) -> Task<AppCommand> {
+        self.char = char;
+        self.new_chat();
+        self.open_session(session);
+        self.chat.select_path(path);
+        self.chat.scroll_to(path.len().saturating_sub(1))
+    }
+
+    pub fn fork(&mut self, idx: usize) {
+        self.abort_streams();
+        self.chat = self.chat.fork(idx);
+        self.history.clear();
+        if let Some(tree) = &mut self.tree {
+            tree.clear();
+        }
+        self.session = SessionLoader::new_session_path(&self.char);
+        self.save();
+    }
+
+    pub fn open_session(&mut self, session: PathBuf) {
+        match Chat::load(&session, &self.char, &self.user) {
+            Ok(chat) => {
+                self.abort_streams();
+                self.chat = chat;
+                self.refresh_user();
+                self.history.clear();
+                if let Some(tree) = &mut self.tree {
+                    tree.clear();
+                }
+                self.session = session;
+            }
+            Err(e) => error!("{e}"),
+        }
+    }
+
+    fn save(&self) {
+        if let Err(e) = self.chat.save(&self.session) {
+            error!("{e}")
+        }
     }
 
     pub fn view<'a>(&'a self, settings: &'a Settings) -> Element<'a, AppCommand> {
-        iced::widget::column![
-            bold_text(
-                format!("{}'s chat with {}", self.user.name(), self.char.name()),
-                settings
-            ),
-            self.chat.view(settings),
-            row![
-                TextEditor::new(&self.input_message)
-                    .size(settings.font_size())
-                    .key_binding(crate::utils::binds::from_key_press)
-                    .on_action(|a| ChatCommand::InputChange(a).into()),
-                button("Submit", settings).on_press(ChatCommand::InputSubmit.into())
-            ]
-            .spacing(10),
-        ]
-        .align_x(Alignment::Center)
-        .padding(20)
-        .spacing(10)
-        .into()
+        let mut chat = Row::new().spacing(10);
+        if let Some(tree) = &self.tree {
+            chat =
+                chat.push(container(tree.view(&self.chat, settings)).width(Length::FillPortion(2)));
+        }
+        let highlight = self.find.as_ref().map(|f| f.query());
+        chat =
+            chat.push(container(self.chat.view(highlight, settings)).width(Length::FillPortion(5)));
+
+        let mut page = Column::new()
+            .align_x(Alignment::Center)
+            .padding(20)
+            .spacing(10)
+            .push(
+                row![bold_text(
+                    format!("{}'s chat with {}", self.user.name(), self.char.name()),
+                    settings
+                )]
+                .push_maybe(self.user_lock.map(|lock| {
+                    text(
+                        match lock {
+                            UserLock::Char => format!("Persona locked to {}", self.char.name()),
+                            UserLock::Chat => "Persona locked to this chat".to_string(),
+                        },
+                        settings,
+                    )
+                }))
+                .push(button("Tree", settings).on_press(ChatCommand::ToggleTree.into()))
+                .push(button("Find", settings).on_press(ChatCommand::ToggleFind.into()))
+                .push(button("Export", settings).on_press(ChatCommand::ToggleExport.into()))
+                .align_y(Alignment::Center)
+                .spacing(10),
+            );
+        if let Some(find) = &self.find {
+            page = page.push(find.view(settings));
+        }
+        if let Some(full_tree) = self.export {
+            let mut export_bar = Row::new().align_y(Alignment::Center).spacing(10);
+            for format in ExportFormat::ALL {
+                export_bar = export_bar.push(
+                    iced::widget::button(text(format.to_string(), settings))
+                        .on_press(ChatCommand::Export(format).into()),
+                );
+            }
+            page = page.push(
+                export_bar
+                    .push(
+                        checkbox("Full tree", full_tree)
+                            .size(settings.font_size())
+                            .on_toggle(|t| ChatCommand::ExportFullTree(t).into()),
+                    )
+                    .push_maybe(
+                        self.exported
+                            .as_ref()
+                            .map(|path| text(format!("Exported to {}", path.display()), settings)),
+                    ),
+            );
+        }
+        page.push(chat)
+            .push(
+                row![
+                    TextEditor::new(&self.input_message)
+                        .size(settings.font_size())
+                        .key_binding(crate::utils::binds::from_key_press)
+                        .on_action(|a| ChatCommand::InputChange(a).into()),
+                    button("Submit", settings).on_press(ChatCommand::InputSubmit.into())
+                ]
+                .spacing(10),
+            )
+            .into()
     }
 
     pub fn update(&mut self, chat_command: ChatCommand, settings: &Settings) -> Task<AppCommand> {
@@ -123,44 +378,302 @@ impl ChatPage {
             ChatCommand::InputSubmit => {
                 let text = self.input_message.text().trim().to_string();
                 if !text.is_empty() {
-                    self.chat.push(Message::from_user(self.user.clone(), text));
+                    self.history.record(self.chat.clone());
+                    self.chat.push(Message::from_input(self.user.clone(), text));
                     self.input_message = Content::new();
+                    self.save();
                 }
                 return Task::done(ChatCommand::GenerateNextMessage.into());
             }
             ChatCommand::GenerateNextMessage => {
                 let chat_history = self.chat.get_chat_messages();
+                self.history.record(self.chat.clone());
                 self.chat.push(Message::empty_from_char(self.char.clone()));
                 return self.get_response(settings, chat_history);
             }
-            ChatCommand::StreamOk(text) => self.chat.append_last_message(text.as_str()),
+            ChatCommand::StreamToOk(path, text) => self.chat.append_to(&path, &text),
+            ChatCommand::StreamEnd(id) => {
+                self.streams.remove(&id);
+                self.save();
+            }
+            ChatCommand::Undo => {
+                self.abort_streams();
+                if let Some(chat) = self.history.undo(self.chat.clone()) {
+                    self.chat = chat;
+                    self.save();
+                }
+            }
+            ChatCommand::Redo => {
+                self.abort_streams();
+                if let Some(chat) = self.history.redo(self.chat.clone()) {
+                    self.chat = chat;
+                    self.save();
+                }
+            }
+            ChatCommand::ToggleTree => {
+                self.tree = match self.tree {
+                    None => Some(TreeView::default()),
+                    Some(_) => None,
+                }
+            }
+            ChatCommand::SelectPath(path) => {
+                self.history.record(self.chat.clone());
+                self.chat.select_path(&path);
+                self.save();
+            }
+            ChatCommand::ToggleCollapse(path) => {
+                if let Some(tree) = &mut self.tree {
+                    tree.toggle_collapse(path);
+                }
+            }
+            ChatCommand::ToggleFind => match self.find {
+                None => {
+                    self.find = Some(Find::default());
+                    return text_input::focus(Find::input_id());
+                }
+                Some(_) => self.find = None,
+            },
+            ChatCommand::FindQuery(query) => {
+                if let Some(find) = &mut self.find {
+                    find.set_query(query, &self.chat);
+                    let path = find.current();
+                    return self.jump_to(path);
+                }
+            }
+            ChatCommand::FindAllBranches(all_branches) => {
+                if let Some(find) = &mut self.find {
+                    find.set_all_branches(all_branches, &self.chat);
+                    let path = find.current();
+                    return self.jump_to(path);
+                }
+            }
+            ChatCommand::FindNext => {
+                if let Some(find) = &mut self.find {
+                    let path = find.next();
+                    return self.jump_to(path);
+                }
+            }
+            ChatCommand::FindPrevious => {
+                if let Some(find) = &mut self.find {
+                    let path = find.previous();
+                    return self.jump_to(path);
+                }
+            }
+            ChatCommand::ToggleExport => {
+                self.exported = None;
+                self.export = match self.export {
+                    None => Some(false),
+                    Some(_) => None,
+                }
+            }
+            ChatCommand::ExportFullTree(full_tree) => self.export = Some(full_tree),
+            ChatCommand::Export(format) => {
+                let full_tree = self.export.unwrap_or_default();
+                return match Exporter::export_to_file(
+                    &self.chat,
+                    &self.char,
+                    &self.user,
+                    &self.session,
+                    format,
+                    full_tree,
+                ) {
+                    Ok(path) => {
+                        trace!("Exported chat to {}", path.display());
+                        self.exported = Some(path);
+                        Task::none()
+                    }
+                    Err(e) => Task::done(AppCommand::Error(e.to_string())),
+                };
+            }
             ChatCommand::MessageCommand(message_command) => match message_command {
                 MessageCommand::Next(idx) => {
+                    self.history.record(self.chat.clone());
                     if self.chat.next(idx, self.char.clone()) {
                         return self.get_response(settings, self.chat.get_chat_messages_until(idx));
                     }
+                    self.save();
+                }
+                MessageCommand::GenerateAlternatives(idx) => {
+                    self.history.record(self.chat.clone());
+                    let chat_history = self.chat.get_chat_messages_until(idx);
+                    let paths = self.chat.add_alternatives(
+                        idx,
+                        settings.alternatives() as usize,
+                        &self.char,
+                    );
+                    return Task::batch(
+                        paths
+                            .into_iter()
+                            .map(|path| self.get_response_to(settings, chat_history.clone(), path)),
+                    );
+                }
+                MessageCommand::Previous(idx) => {
+                    self.history.record(self.chat.clone());
+                    self.chat.previous(idx);
+                    self.save();
                 }
-                MessageCommand::Previous(idx) => self.chat.previous(idx),
                 MessageCommand::ToggleEdit(idx) => {
+                    let snapshot = self.chat.clone();
                     if self.chat.toggle_edit(idx) {
+                        self.history.record(snapshot);
+                        self.save();
                         return Task::done(ChatCommand::GenerateNextMessage.into());
                     }
                 }
+                MessageCommand::SaveEdit(idx) => {
+                    self.history.record(self.chat.clone());
+                    self.chat.save_edit(idx);
+                    self.save();
+                }
                 MessageCommand::AbortEdit(idx) => self.chat.abort_edit(idx),
                 MessageCommand::EditAction(idx, action) => self.chat.perform_action(idx, action),
-                MessageCommand::Delete(idx) => self.chat.delete(idx),
+                MessageCommand::ToggleExcluded(idx) => {
+                    self.history.record(self.chat.clone());
+                    self.chat.toggle_excluded(idx);
+                    self.save();
+                }
+                MessageCommand::ToggleLore(idx) => self.chat.toggle_lore(idx),
+                MessageCommand::Fork(idx) => self.fork(idx),
+                MessageCommand::Delete(idx) => {
+                    self.abort_streams();
+                    self.history.record(self.chat.clone());
+                    self.chat.delete(idx);
+                    self.save();
+                }
             },
         }
         Task::none()
     }
 
-    fn get_response(&self, settings: &Settings, messages: Vec<ChatMessage>) -> Task<AppCommand> {
-        let llm = settings.llm(&self.char, &self.user);
-        Task::perform(async move { llm.chat_stream(&messages).await }, |res| res).and_then(|res| {
-            Task::run(res, |chunk| match chunk {
-                Ok(text) => ChatCommand::StreamOk(text).into(),
-                Err(e) => AppCommand::Error(e.to_string()),
+    /// Selects the branch holding the message at `path` and scrolls to it.
+    fn jump_to(&mut self, path: Option<Vec<usize>>) -> Task<AppCommand> {
+        let Some(path) = path else {
+            return Task::none();
+        };
+        if !self.chat.selected_path().starts_with(&path) {
+            self.history.record(self.chat.clone());
+            self.chat.select_path(&path);
+            self.save();
+        }
+        self.chat.scroll_to(path.len() - 1)
+    }
+
+    pub fn char(&self) -> &Persona {
+        &self.char
+    }
+
+    pub fn worlds(&self) -> &[String] {
+        self.chat.worlds()
+    }
+
+    /// Attaches a world book to the current chat, or detaches it.
+    pub fn set_world(&mut self, world: String, attached: bool) {
+        self.chat.set_world(world, attached);
+        self.save();
+    }
+
+    /// The char's system prompt with the lore triggered by `messages`, from
+    /// the char's own book and the worlds of the char, the chat and the settings.
+    fn system_prompt(&self, settings: &Settings, messages: &[ChatMessage]) -> (String, Report) {
+        let mut books: Vec<CharacterBook> = self
+            .char
+            .card()
+            .data
+            .character_book
+            .into_iter()
+            .map(|mut book| {
+                book.name
+                    .get_or_insert(format!("{}'s lorebook", self.char.name()));
+                book
             })
-        })
+            .collect();
+        let mut worlds: Vec<String> = vec![];
+        for world in self
+            .char
+            .world()
+            .into_iter()
+            .chain(self.chat.worlds().iter().cloned())
+            .chain(settings.worlds().iter().cloned())
+        {
+            if !worlds.contains(&world) {
+                worlds.push(world)
+            }
+        }
+        for world in worlds {
+            match WorldLoader::load(&world) {
+                Ok(mut world) => {
+                    world.book.name.get_or_insert(world.name);
+                    books.push(world.book)
+                }
+                Err(e) => error!("World {world}: {e}"),
+            }
+        }
+        let texts: Vec<&str> = messages.iter().map(|m| m.content.as_str()).collect();
+        let activation = lore::activate(&books, &texts);
+        // The user persona's description follows the char's definition.
+        let char_prompt = [
+            self.char.system_prompt(Some(self.user.name())),
+            self.user.card().data.description,
+        ]
+        .into_iter()
+        .filter(|p| !p.trim().is_empty())
+        .collect::<Vec<String>>()
+        .join("\n");
+        let prompt = lore::system_prompt(
+            &char_prompt,
+            &activation.entries,
+            self.char.name(),
+            self.user.name(),
+        );
+        (prompt, activation.report)
+    }
+
+    /// Streams the reply to `messages` into the last message of the selected path.
+    fn get_response(
+        &mut self,
+        settings: &Settings,
+        messages: Vec<ChatMessage>,
+    ) -> Task<AppCommand> {
+        let path = self.chat.selected_path();
+        self.get_response_to(settings, messages, path)
+    }
+
+    /// Streams the reply to `messages` into the message at `path`.
+    fn get_response_to(
+        &mut self,
+        settings: &Settings,
+        messages: Vec<ChatMessage>,
+        path: Vec<usize>,
+    ) -> Task<AppCommand> {
+        let (prompt, report) = self.system_prompt(settings, &messages);
+        self.chat.set_lore(&path, report);
+        let llm = settings.llm(prompt);
+        let task = Task::perform(async move { llm.chat_stream(&messages).await }, |res| res)
+            .and_then(move |res| {
+                let path = path.clone();
+                Task::run(res, move |chunk| match chunk {
+                    Ok(text) => ChatCommand::StreamToOk(path.clone(), text).into(),
+                    Err(e) => AppCommand::Error(e.to_string()),
+                })
+            });
+        self.stream(task)
+    }
+
+    /// Ends `task` with a [`ChatCommand::StreamEnd`] and keeps it abortable.
+    fn stream(&mut self, task: Task<AppCommand>) -> Task<AppCommand> {
+        let id = self.next_stream;
+        self.next_stream += 1;
+        let (task, handle) = task
+            .chain(Task::done(ChatCommand::StreamEnd(id).into()))
+            .abortable();
+        self.streams.insert(id, handle);
+        task
+    }
+
+    /// Stops the replies being streamed, they stay as far as they got.
+    fn abort_streams(&mut self) {
+        for (_, stream) in self.streams.drain() {
+            stream.abort();
+        }
     }
 }
@@ -0,0 +1,127 @@
+use anyhow::{Result, anyhow};
+use chrono::Local;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::{persona::Persona, utils::files};
+
+pub struct SessionLoader {}
+
+impl SessionLoader {
+    pub fn new_session_path(char: &Persona) -> PathBuf {
+        let dir = Self::chats_path(char.name());
+        let stem = Local::now().format("%Y-%m-%d_%H-%M-%S%.3f").to_string();
+        let mut path = dir.join(format!("{stem}.json"));
+        let mut n = 1;
+        while path.exists() {
+            path = dir.join(format!("{stem}-{n}.json"));
+            n += 1;
+        }
+        path
+    }
+
+    pub fn most_recent_session(char: &Persona) -> Result<PathBuf> {
+        let mut most_recent_file: Result<PathBuf> = Err(anyhow!("No session found"));
+        let mut most_recent_change = SystemTime::UNIX_EPOCH;
+        for path in Self::sessions(char.name())? {
+            let modified_time = Self::modified_time(&path);
+            if modified_time > most_recent_change {
+                most_recent_change = modified_time;
+                most_recent_file = Ok(path)
+            }
+        }
+        most_recent_file
+    }
+
+    pub fn sessions(char_name: &str) -> Result<Vec<PathBuf>> {
+        let mut sessions = vec![];
+        for entry in (fs::read_dir(Self::chats_path(char_name))?).flatten() {
+            let path = entry.path();
+            if path.is_file() && path.extension().is_some_and(|ext| ext == "json") {
+                sessions.push(path);
+            }
+        }
+        Ok(sessions)
+    }
+
+    /// Every saved session as (character directory, session path), the
+    /// directory is the character name passed through [`files::file_name`].
+    pub fn all_sessions() -> Result<Vec<(String, PathBuf)>> {
+        let mut sessions = vec![];
+        for entry in (fs::read_dir(Self::chats_root())?).flatten() {
+            let path = entry.path();
+            if path.is_dir()
+                && let Some(char_name) = path.file_name().and_then(|n| n.to_str())
+                && let Ok(char_sessions) = Self::sessions(char_name)
+            {
+                for session in char_sessions {
+                    sessions.push((char_name.to_string(), session));
+                }
+            }
+        }
+        Ok(sessions)
+    }
+
+    /// Moves the sessions of a renamed character under its new name.
+    pub fn rename_char(old_name: &str, new_name: &str) -> Result<()> {
+        let old = Self::chats_path(old_name);
+        let new = Self::chats_path(new_name);
+        if old == new || !old.exists() {
+            return Ok(());
+        }
+        if !new.exists() {
+            fs::rename(old, new)?;
+            return Ok(());
+        }
+        for session in Self::sessions(old_name)? {
+            if let Some(file) = session.file_name() {
+                fs::rename(&session, new.join(file))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The directory of a character's sessions, named so no card name can
+    /// point outside the chats directory.
+    pub fn chats_path(char_name: &str) -> PathBuf {
+        Self::chats_root().join(files::file_name(char_name))
+    }
+
+    fn chats_root() -> PathBuf {
+        dirs::cache_dir()
+            .map(|mut path| {
+                path.push("fullmoon");
+                path.push("chats");
+                path
+            })
+            .unwrap()
+    }
+
+    fn modified_time(path: &Path) -> SystemTime {
+        if let Ok(metadata) = fs::metadata(path)
+            && let Ok(modified_time) = metadata.modified()
+        {
+            return modified_time;
+        }
+        SystemTime::UNIX_EPOCH
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SessionLoader;
+
+    #[test]
+    fn chats_stay_in_the_chats_directory() {
+        let root = SessionLoader::chats_root();
+        for name in ["../../x", "a/b", "..", "C:\\x"] {
+            assert_eq!(
+                SessionLoader::chats_path(name).parent(),
+                Some(root.as_path())
+            );
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use std::collections::HashSet;
+
+use iced::{
+    Alignment, Border, Element,
+    Length::Fill,
+    Theme,
+    widget::{Space, button, container, keyed, row, scrollable},
+};
+use iced_modern_theme::colors::colors;
+
+use crate::{
+    AppCommand,
+    chat_page::{ChatCommand, chat::Chat},
+    settings::Settings,
+    utils::widgets::text,
+};
+
+const PREVIEW_LENGTH: usize = 40;
+const INDENT: f32 = 16.0;
+
+/// Side panel drawing every branch of the chat tree.
+#[derive(Default)]
+pub struct TreeView {
+    collapsed: HashSet<Vec<usize>>,
+}
+
+impl TreeView {
+    pub fn toggle_collapse(&mut self, path: Vec<usize>) {
+        if !self.collapsed.remove(&path) {
+            self.collapsed.insert(path);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.collapsed.clear();
+    }
+
+    pub fn view<'a>(&'a self, chat: &'a Chat, settings: &'a Settings) -> Element<'a, AppCommand> {
+        let mut keyed_column = keyed::Column::new().spacing(2).padding(10);
+        for (key, item) in chat.tree_items(&self.collapsed).into_iter().enumerate() {
+            let depth = item.path.len() - 1;
+            let toggle: Element<'a, AppCommand> = match item.nb_childs {
+                0 => Space::with_width(settings.font_size()).into(),
+                _ => button(text(if item.collapsed { "+" } else { "-" }, settings))
+                    .padding(0)
+                    .style(button::text)
+                    .on_press(ChatCommand::ToggleCollapse(item.path.clone()).into())
+                    .into(),
+            };
+            let active = item.active;
+            keyed_column = keyed_column.push(
+                key,
+                row![
+                    Space::with_width(INDENT * depth as f32),
+                    toggle,
+                    button(text(
+                        format!(
+                            "{}: {}",
+                            item.message.owner.name(),
+                            Self::preview(&item.message.text)
+                        ),
+                        settings
+                    ))
+                    .width(Fill)
+                    .style(move |theme, status| Self::node_style(theme, status, active))
+                    .on_press(ChatCommand::SelectPath(item.path).into()),
+                ]
+                .align_y(Alignment::Center)
+                .spacing(4),
+            );
+        }
+        container(scrollable(keyed_column).height(Fill).width(Fill))
+            .style(Self::panel_style)
+            .into()
+    }
+
+    fn preview(text: &str) -> String {
+        let line = text.split_whitespace().collect::<Vec<&str>>().join(" ");
+        match line.char_indices().nth(PREVIEW_LENGTH) {
+            Some((end, _)) => format!("{}…", &line[..end]),
+            None => line,
+        }
+    }
+
+    fn node_style(theme: &Theme, status: button::Status, active: bool) -> button::Style {
+        let style = button::secondary(theme, status);
+        match active {
+            true => style.with_background(colors::system::BLUE_DARK),
+            false => style.with_background(colors::fill::TERTIARY_DARK),
+        }
+    }
+
+    fn panel_style(theme: &Theme) -> iced::widget::container::Style {
+        container::rounded_box(theme)
+            .background(colors::fill::SECONDARY_DARK)
+            .border(Border::default().rounded(12))
+    }
+}
@@ -0,0 +1,391 @@
+use std::{fs, path::PathBuf};
+
+use anyhow::{Result, anyhow};
+use log::{error, trace};
+use regex::{Captures, Regex};
+use serde_json::{Value, json};
+
+use crate::{
+    chat_page::{chat::Chat, session::SessionLoader},
+    message::{Message, OwnerType},
+    persona::{
+        Persona,
+        loader::{PersonaLoader, Subdir},
+    },
+    settings::Settings,
+    utils::files,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DatasetFormat {
+    /// One conversation per line, following the selected path of each chat.
+    ShareGpt,
+    /// One `{prompt, chosen, rejected}` line per rejected sibling of a selected reply.
+    Dpo,
+}
+
+#[derive(Default)]
+pub struct DatasetOptions {
+    /// Only use the chats of this character.
+    pub char: Option<String>,
+    /// Replies shorter than this many characters are not used.
+    pub min_length: usize,
+    /// Replaces character and user names with `{{char}}` and `{{user}}`.
+    pub anonymize: bool,
+}
+
+pub struct Dataset {}
+
+impl Dataset {
+    /// Builds a JSONL dataset out of every saved chat.
+    pub fn export(format: DatasetFormat, options: &DatasetOptions) -> Result<String> {
+        let chars = PersonaLoader::load_from_cache(Subdir::Chars);
+        let selected = PersonaLoader::load_selected_user(Settings::load().user());
+        let mut lines = vec![];
+        for (char_dir, session) in SessionLoader::all_sessions()? {
+            if options
+                .char
+                .as_ref()
+                .is_some_and(|c| files::file_name(c) != char_dir)
+            {
+                continue;
+            }
+            let char = chars
+                .iter()
+                .find(|c| files::file_name(c.name()) == char_dir)
+                .cloned()
+                .unwrap_or_else(Persona::default_char);
+            let mut chat = match Chat::load(&session, &char, &selected) {
+                Ok(chat) => chat,
+                Err(e) => {
+                    error!("{}: {e}", session.display());
+                    continue;
+                }
+            };
+            // The persona locked to the chat or the char spoke for the user.
+            let lock = chat.user_lock().map(str::to_string).or(char.user_lock());
+            let user = PersonaLoader::load_locked_user(lock.as_deref(), &selected);
+            chat.set_owners(&char, &user);
+            let anonymizer = Anonymizer::new(&char, &user, options.anonymize);
+            match format {
+                DatasetFormat::ShareGpt => {
+                    lines.extend(Self::sharegpt(&chat, &char, &user, options, &anonymizer))
+                }
+                DatasetFormat::Dpo => {
+                    lines.extend(Self::dpo(&chat, &char, &user, options, &anonymizer))
+                }
+            }
+        }
+        trace!("Built {} dataset lines", lines.len());
+        Ok(lines
+            .iter()
+            .map(|line| format!("{line}\n"))
+            .collect::<String>())
+    }
+
+    fn sharegpt(
+        chat: &Chat,
+        char: &Persona,
+        user: &Persona,
+        options: &DatasetOptions,
+        anonymizer: &Anonymizer,
+    ) -> Option<Value> {
+        let messages: Vec<Message> = chat
+            .get_current_chat()
+            .into_iter()
+            .filter(|m| !m.excluded)
+            .collect();
+        if !messages.iter().any(|m| m.owner_type == OwnerType::Char)
+            || messages
+                .iter()
+                .any(|m| m.owner_type == OwnerType::Char && Self::too_short(m, options))
+        {
+            return None;
+        }
+        let mut conversations = vec![json!({
+            "from": "system",
+            "value": anonymizer.apply(&char.system_prompt(Some(user.name()))),
+        })];
+        conversations.extend(messages.iter().map(|m| {
+            json!({
+                "from": match m.owner_type {
+                    OwnerType::Char => "gpt",
+                    _ => "human",
+                },
+                "value": anonymizer.apply(&Self::content(m)),
+            })
+        }));
+        Some(json!({ "conversations": conversations }))
+    }
+
+    fn dpo(
+        chat: &Chat,
+        char: &Persona,
+        user: &Persona,
+        options: &DatasetOptions,
+        anonymizer: &Anonymizer,
+    ) -> Vec<Value> {
+        let mut lines = vec![];
+        let mut prompt = vec![json!({
+            "role": "system",
+            "content": anonymizer.apply(&char.system_prompt(Some(user.name()))),
+        })];
+        for (siblings, selected) in chat.selected_levels() {
+            let chosen = siblings[selected];
+            if chosen.owner_type == OwnerType::Char && !Self::too_short(chosen, options) {
+                for (idx, rejected) in siblings.iter().enumerate() {
+                    if idx == selected
+                        || rejected.owner_type != OwnerType::Char
+                        || Self::too_short(rejected, options)
+                    {
+                        continue;
+                    }
+                    lines.push(json!({
+                        "prompt": prompt,
+                        "chosen": [Self::turn(chosen, anonymizer)],
+                        "rejected": [Self::turn(rejected, anonymizer)],
+                    }));
+                }
+            }
+            if !chosen.excluded {
+                prompt.push(Self::turn(chosen, anonymizer));
+            }
+        }
+        lines
+    }
+
+    fn too_short(message: &Message, options: &DatasetOptions) -> bool {
+        message.text.chars().count() < options.min_length
+    }
+
+    fn turn(message: &Message, anonymizer: &Anonymizer) -> Value {
+        json!({
+            "role": match message.owner_type {
+                OwnerType::Char => "assistant",
+                _ => "user",
+            },
+            "content": anonymizer.apply(&Self::content(message)),
+        })
+    }
+
+    /// Same tagging of system and narrator messages as the prompts sent to the model.
+    fn content(message: &Message) -> String {
+        match message.owner_type {
+            OwnerType::System => format!("[System: {}]", message.text),
+            OwnerType::Narrator => format!("[Narrator: {}]", message.text),
+            _ => message.text.clone(),
+        }
+    }
+
+    /// Headless entry point:
+    /// `fullmoon dataset <sharegpt|dpo> [--char NAME] [--min-length N] [--anonymize] [--out FILE]`.
+    pub fn cli(args: &[String]) -> Result<()> {
+        let usage = "usage: fullmoon dataset <sharegpt|dpo> [--char NAME] [--min-length N] [--anonymize] [--out FILE]";
+        let format = match args.first().map(|a| a.as_str()) {
+            Some("sharegpt") => DatasetFormat::ShareGpt,
+            Some("dpo") => DatasetFormat::Dpo,
+            _ => return Err(anyhow!(usage)),
+        };
+        let value = |flag: &str| -> Result<Option<&String>> {
+            args.iter()
+                .position(|a| a == flag)
+                .map(|i| args.get(i + 1).ok_or(anyhow!(usage)))
+                .transpose()
+        };
+        let options = DatasetOptions {
+            char: value("--char")?.cloned(),
+            min_length: value("--min-length")?
+                .map(|n| n.parse())
+                .transpose()?
+                .unwrap_or_default(),
+            anonymize: args.iter().any(|a| a == "--anonymize"),
+        };
+        let dataset = Self::export(format, &options)?;
+        match value("--out")? {
+            Some(out) => fs::write(PathBuf::from(out), dataset)?,
+            None => print!("{dataset}"),
+        }
+        Ok(())
+    }
+}
+
+struct Anonymizer {
+    /// Every name as a whole word, longest first.
+    pattern: Option<Regex>,
+    names: Vec<(String, &'static str)>,
+}
+
+impl Anonymizer {
+    fn new(char: &Persona, user: &Persona, enabled: bool) -> Self {
+        let mut names = vec![];
+        if enabled {
+            names.push((char.name().to_string(), "{{char}}"));
+            names.push((user.name().to_string(), "{{user}}"));
+            // Longest first so a name containing the other one is replaced whole.
+            names.sort_by_key(|(name, _)| std::cmp::Reverse(name.len()));
+            names.retain(|(name, _)| !name.is_empty());
+        }
+        let word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+        let alternatives: Vec<String> = names
+            .iter()
+            .map(|(name, _)| {
+                format!(
+                    "{}{}{}",
+                    if word(name.chars().next()) { r"\b" } else { "" },
+                    regex::escape(name),
+                    if word(name.chars().last()) { r"\b" } else { "" },
+                )
+            })
+            .collect();
+        let pattern = match alternatives.is_empty() {
+            true => None,
+            false => Regex::new(&alternatives.join("|")).ok(),
+        };
+        Anonymizer { pattern, names }
+    }
+
+    fn apply(&self, text: &str) -> String {
+        let Some(pattern) = &self.pattern else {
+            return text.to_string();
+        };
+        pattern
+            .replace_all(text, |caps: &Captures| {
+                self.names
+                    .iter()
+                    .find(|(name, _)| *name == caps[0])
+                    .map_or(&caps[0], |(_, placeholder)| *placeholder)
+                    .to_string()
+            })
+            .into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{rc::Rc, time::SystemTime};
+
+    use iced::widget::image::Handle;
+
+    use super::{Anonymizer, Dataset, DatasetOptions};
+    use crate::{
+        chat_page::chat::Chat,
+        message::Message,
+        persona::{Persona, card::Card},
+    };
+
+    fn persona(name: &str) -> Persona {
+        Persona::new(
+            Rc::new(Card::new(name, "You are {{char}}, talking to {{user}}.")),
+            Handle::from_path("assets/char.png"),
+            SystemTime::now(),
+            Default::default(),
+        )
+    }
+
+    /// A request answered by three alternatives, the second one selected.
+    fn branched_chat() -> (Chat, Persona, Persona) {
+        let (char, user) = (Persona::default_char(), Persona::default_user());
+        let mut chat = Chat::default();
+        chat.push(Message::from_user(user.clone(), "Sing for me".to_string()));
+        chat.push_alternatives(
+            vec![
+                Message::from_char(char.clone(), "No.".to_string()),
+                Message::from_char(char.clone(), "*chante l'été*".to_string()),
+                Message::from_char(char.clone(), "*hums a lullaby*".to_string()),
+            ],
+            1,
+        );
+        chat.push(Message::from_user(user.clone(), "Thanks".to_string()));
+        (chat, char, user)
+    }
+
+    fn options(min_length: usize) -> DatasetOptions {
+        DatasetOptions {
+            min_length,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn sharegpt_follows_the_selected_branch() {
+        let (chat, char, user) = branched_chat();
+        let anonymizer = Anonymizer::new(&char, &user, false);
+        let line = Dataset::sharegpt(&chat, &char, &user, &options(0), &anonymizer).unwrap();
+        let turns: Vec<(&str, &str)> = line["conversations"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|t| (t["from"].as_str().unwrap(), t["value"].as_str().unwrap()))
+            .skip(1)
+            .collect();
+        assert_eq!(
+            turns,
+            [
+                ("human", "Sing for me"),
+                ("gpt", "*chante l'été*"),
+                ("human", "Thanks")
+            ]
+        );
+
+        // The reply is 14 characters long, and 16 bytes.
+        assert!(Dataset::sharegpt(&chat, &char, &user, &options(14), &anonymizer).is_some());
+        assert!(Dataset::sharegpt(&chat, &char, &user, &options(15), &anonymizer).is_none());
+    }
+
+    #[test]
+    fn dpo_pairs_the_selected_reply_with_its_siblings() {
+        let (chat, char, user) = branched_chat();
+        let anonymizer = Anonymizer::new(&char, &user, false);
+        let rejected = |min_length: usize| -> Vec<String> {
+            Dataset::dpo(&chat, &char, &user, &options(min_length), &anonymizer)
+                .iter()
+                .map(|line| {
+                    assert_eq!(line["prompt"].as_array().unwrap().len(), 2);
+                    assert_eq!(line["prompt"][1]["content"], "Sing for me");
+                    assert_eq!(line["chosen"][0]["content"], "*chante l'été*");
+                    line["rejected"][0]["content"].as_str().unwrap().to_string()
+                })
+                .collect()
+        };
+        assert_eq!(rejected(0), ["No.", "*hums a lullaby*"]);
+        assert_eq!(rejected(4), ["*hums a lullaby*"]);
+        // A chosen reply too short makes no pair at all.
+        assert!(rejected(15).is_empty());
+    }
+
+    #[test]
+    fn anonymizing_replaces_whole_names() {
+        let (char, user) = (persona("Al"), persona("User"));
+        let anonymizer = Anonymizer::new(&char, &user, true);
+        assert_eq!(
+            anonymizer.apply("Always ask Al. User, Users and username differ."),
+            "Always ask {{char}}. {{user}}, Users and username differ."
+        );
+
+        // A name containing the other one is replaced whole.
+        let (char, user) = (persona("Aria"), persona("Aria Lee"));
+        let anonymizer = Anonymizer::new(&char, &user, true);
+        assert_eq!(
+            anonymizer.apply("Aria Lee met Aria in Ariadne."),
+            "{{user}} met {{char}} in Ariadne."
+        );
+
+        let mut chat = Chat::default();
+        chat.push(Message::from_user(user.clone(), "Hi Aria".to_string()));
+        chat.push(Message::from_char(
+            char.clone(),
+            "Hello Aria Lee".to_string(),
+        ));
+        let options = DatasetOptions {
+            anonymize: true,
+            ..Default::default()
+        };
+        let line = Dataset::sharegpt(&chat, &char, &user, &options, &anonymizer).unwrap();
+        assert_eq!(
+            line["conversations"][0]["value"],
+            "You are {{char}}, talking to {{user}}."
+        );
+        assert_eq!(line["conversations"][1]["value"], "Hi {{char}}");
+        assert_eq!(line["conversations"][2]["value"], "Hello {{user}}");
+    }
+}
@@ -0,0 +1,61 @@
+use crate::export::ExportedMessage;
+
+/// Longest text shown in a node label, in characters.
+const LABEL_LENGTH: usize = 60;
+
+/// Expects the full tree, each message carrying its branch label.
+pub fn export(title: &str, messages: &[ExportedMessage]) -> String {
+    let mut out = format!(
+        "digraph chat {{\n  label=\"{}\";\n  labelloc=t;\n  node [shape=box, style=\"rounded,filled\", fillcolor=white, fontname=\"sans-serif\"];\n",
+        escape(title)
+    );
+    for message in messages {
+        let Some(label) = &message.label else {
+            continue;
+        };
+        let mut text: String = message.text.chars().take(LABEL_LENGTH).collect();
+        if message.text.chars().count() > LABEL_LENGTH {
+            text.push('…');
+        }
+        let mut style = vec![];
+        if message.active {
+            style.push("color=\"#3478f6\", penwidth=2, fillcolor=\"#dbe8fd\"");
+        }
+        if message.excluded {
+            style.push("fontcolor=gray");
+        }
+        out.push_str(&format!(
+            "  {} [label=\"{}\\n{}\"{}{}];\n",
+            id(label),
+            escape(message.owner.name()),
+            escape(&text),
+            if style.is_empty() { "" } else { ", " },
+            style.join(", ")
+        ));
+        if let Some((parent, _)) = label.rsplit_once('.') {
+            out.push_str(&format!(
+                "  {} -> {}{};\n",
+                id(parent),
+                id(label),
+                if message.active {
+                    " [color=\"#3478f6\", penwidth=2]"
+                } else {
+                    ""
+                }
+            ));
+        }
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// "1.2.1" becomes `n1_2_1`.
+fn id(label: &str) -> String {
+    format!("n{}", label.replace('.', "_"))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use base64::{Engine, engine::general_purpose::STANDARD};
+use iced::{Color, font::Style};
+use iced_modern_theme::colors::colors;
+use log::error;
+
+use crate::{export::ExportedMessage, formater::Formater, message::OwnerType};
+
+pub fn export(title: &str, messages: &[ExportedMessage]) -> Result<String> {
+    let mut avatars: HashMap<&str, String> = HashMap::new();
+    let mut body = String::new();
+    for message in messages {
+        let name = message.owner.name();
+        if matches!(message.owner_type, OwnerType::User | OwnerType::Char)
+            && !avatars.contains_key(name)
+        {
+            match message.owner.avatar_png() {
+                Ok(png) => {
+                    avatars.insert(
+                        name,
+                        format!("data:image/png;base64,{}", STANDARD.encode(png)),
+                    );
+                }
+                Err(e) => error!("{e}"),
+            }
+        }
+
+        let mut classes = vec!["message", class(message.owner_type)];
+        if message.excluded {
+            classes.push("excluded");
+        }
+        if message.label.is_some() && message.active {
+            classes.push("active");
+        }
+        let avatar = match avatars.get(name) {
+            Some(uri) if matches!(message.owner_type, OwnerType::User | OwnerType::Char) => {
+                format!("<img class=\"avatar\" src=\"{uri}\" alt=\"\">")
+            }
+            _ => String::new(),
+        };
+        let label = match &message.label {
+            Some(label) => format!("<span class=\"label\">{label}</span> "),
+            None => String::new(),
+        };
+        body.push_str(&format!(
+            "<div class=\"{}\" style=\"margin-left: {}px\">{}<div class=\"content\"><div class=\"header\">{}<b>{}</b> <span class=\"time\">{}</span></div><div class=\"text\">{}</div></div></div>\n",
+            classes.join(" "),
+            message.depth * 24,
+            avatar,
+            label,
+            escape(name),
+            message.time.format("%B %d, %Y %H:%M"),
+            rich_text(message.text)
+        ));
+    }
+
+    Ok(format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n<style>\n{style}</style>\n</head>\n<body>\n<h1>{title}</h1>\n{body}</body>\n</html>\n",
+        title = escape(title),
+        style = style(),
+        body = body
+    ))
+}
+
+/// Same emphasis and quote styling as the in-app `Formater`.
+fn rich_text(text: &str) -> String {
+    Formater::segments(text)
+        .into_iter()
+        .filter(|(string, _)| !string.is_empty())
+        .map(|(string, string_type)| {
+            let italic = match iced::Font::from(string_type.clone()).style {
+                Style::Italic => "font-style: italic; ",
+                _ => "",
+            };
+            format!(
+                "<span style=\"{}color: {}\">{}</span>",
+                italic,
+                css(Color::from(string_type)),
+                escape(&string).replace('\n', "<br>")
+            )
+        })
+        .collect()
+}
+
+fn class(owner_type: &OwnerType) -> &'static str {
+    match owner_type {
+        OwnerType::User => "user",
+        OwnerType::Char => "char",
+        OwnerType::System => "system",
+        OwnerType::Narrator => "narrator",
+    }
+}
+
+fn style() -> String {
+    format!(
+        "body {{ background: {background}; color: {text}; font-family: sans-serif; max-width: 900px; margin: auto; padding: 20px; }}
+.message {{ display: flex; gap: 10px; background: {message}; border-radius: 12px; padding: 10px; margin-bottom: 10px; }}
+.system {{ background: {background}; border: 1px solid {separator}; text-align: center; justify-content: center; }}
+.narrator {{ background: {narrator}; }}
+.excluded {{ background: {background}; opacity: 0.4; }}
+.active {{ outline: 2px solid {accent}; }}
+.avatar {{ width: 96px; height: 96px; border-radius: 50%; flex-shrink: 0; }}
+.content {{ flex: 1; }}
+.header {{ margin-bottom: 4px; }}
+.time, .label {{ color: {secondary}; }}
+",
+        background = css(colors::fill::BACKGROUND_DARK),
+        text = css(colors::text::PRIMARY_DARK),
+        message = css(colors::fill::SECONDARY_DARK),
+        narrator = css(colors::fill::TERTIARY_DARK),
+        separator = css(colors::state::SEPARATOR_DARK),
+        accent = css(colors::system::BLUE_DARK),
+        secondary = css(colors::text::TERTIARY_DARK),
+    )
+}
+
+fn css(color: Color) -> String {
+    let [r, g, b, _] = color.into_rgba8();
+    format!("#{r:02x}{g:02x}{b:02x}")
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
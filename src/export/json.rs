@@ -0,0 +1,31 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{chat_page::chat::Chat, persona::Persona};
+
+/// The whole chat tree, re-importable as is. Personas are stored by name and
+/// matched again on import, like in saved sessions.
+#[derive(Serialize, Deserialize)]
+pub struct TreeDocument {
+    pub format: String,
+    pub version: u32,
+    pub char: String,
+    pub user: String,
+    pub chat: Chat,
+}
+
+impl TreeDocument {
+    pub const FORMAT: &str = "fullmoon-tree";
+    /// Version 2 saves the chat as a flat node list, version 1 files nest it.
+    pub const VERSION: u32 = 2;
+}
+
+pub fn export(chat: &Chat, char: &Persona, user: &Persona) -> Result<String> {
+    Ok(serde_json::to_string_pretty(&TreeDocument {
+        format: TreeDocument::FORMAT.to_string(),
+        version: TreeDocument::VERSION,
+        char: char.name().to_string(),
+        user: user.name().to_string(),
+        chat: chat.clone(),
+    })?)
+}
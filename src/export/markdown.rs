@@ -0,0 +1,42 @@
+use crate::{export::ExportedMessage, message::OwnerType};
+
+pub fn export(title: &str, messages: &[ExportedMessage]) -> String {
+    let mut out = format!("# {title}\n");
+    for message in messages {
+        out.push_str("\n---\n\n");
+        let label = match &message.label {
+            Some(label) => format!("`{label}` "),
+            None => String::new(),
+        };
+        let mut notes = vec![];
+        if message.label.is_some() && message.active {
+            notes.push("selected");
+        }
+        if message.excluded {
+            notes.push("excluded");
+        }
+        let notes = match notes.is_empty() {
+            true => String::new(),
+            false => format!(" _({})_", notes.join(", ")),
+        };
+        out.push_str(&format!(
+            "{}**{}** · {}{}\n\n",
+            label,
+            message.owner.name(),
+            message.time.format("%B %d, %Y %H:%M"),
+            notes
+        ));
+        match message.owner_type {
+            OwnerType::System | OwnerType::Narrator => {
+                for line in message.text.lines() {
+                    out.push_str(&format!("> {line}\n"));
+                }
+            }
+            _ => {
+                out.push_str(message.text);
+                out.push('\n');
+            }
+        }
+    }
+    out
+}
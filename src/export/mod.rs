@@ -0,0 +1,413 @@
+use std::{
+    collections::HashSet,
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Local};
+use log::trace;
+
+use crate::{
+    chat_page::chat::Chat,
+    message::{Message, OwnerType},
+    persona::{
+        Persona,
+        charx::CharX,
+        loader::{PersonaLoader, Subdir},
+    },
+    settings::Settings,
+    utils::files,
+};
+
+pub mod dataset;
+mod dot;
+mod html;
+pub mod json;
+mod markdown;
+mod text;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Markdown,
+    Html,
+    Text,
+    Dot,
+    Json,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 5] = [
+        ExportFormat::Markdown,
+        ExportFormat::Html,
+        ExportFormat::Text,
+        ExportFormat::Dot,
+        ExportFormat::Json,
+    ];
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "md",
+            ExportFormat::Html => "html",
+            ExportFormat::Text => "txt",
+            ExportFormat::Dot => "dot",
+            ExportFormat::Json => "json",
+        }
+    }
+
+    /// Formats that always contain every branch.
+    pub fn is_tree(&self) -> bool {
+        matches!(self, ExportFormat::Dot | ExportFormat::Json)
+    }
+
+    fn from_extension(extension: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|f| f.extension() == extension)
+    }
+}
+
+impl Display for ExportFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ExportFormat::Markdown => "Markdown",
+            ExportFormat::Html => "HTML",
+            ExportFormat::Text => "Text",
+            ExportFormat::Dot => "Graphviz",
+            ExportFormat::Json => "JSON tree",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CardFormat {
+    /// The avatar with the card in its text chunks.
+    Png,
+    /// A zip with the card and its assets.
+    CharX,
+}
+
+impl CardFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            CardFormat::Png => "png",
+            CardFormat::CharX => "charx",
+        }
+    }
+
+    fn export(&self, char: &Persona) -> Result<Vec<u8>> {
+        match self {
+            CardFormat::Png => char.card_png(),
+            CardFormat::CharX => CharX::export(char),
+        }
+    }
+}
+
+/// A message as it appears in an export, flattened out of the chat tree.
+pub struct ExportedMessage<'a> {
+    pub owner: &'a Persona,
+    pub owner_type: &'a OwnerType,
+    pub text: &'a str,
+    pub time: DateTime<Local>,
+    pub excluded: bool,
+    /// Branch label like "1.2.1", only set for full tree exports.
+    pub label: Option<String>,
+    pub depth: usize,
+    pub active: bool,
+}
+
+impl<'a> ExportedMessage<'a> {
+    fn new(message: &'a Message) -> Self {
+        ExportedMessage {
+            owner: &message.owner,
+            owner_type: &message.owner_type,
+            text: &message.text,
+            time: message.time,
+            excluded: message.excluded,
+            label: None,
+            depth: 0,
+            active: true,
+        }
+    }
+}
+
+pub struct Exporter {}
+
+impl Exporter {
+    /// Renders the selected branch, or every branch when `full_tree` is set.
+    pub fn export(
+        chat: &Chat,
+        char: &Persona,
+        user: &Persona,
+        format: ExportFormat,
+        full_tree: bool,
+    ) -> Result<String> {
+        let full_tree = full_tree || format.is_tree();
+        let branch = chat.get_current_chat();
+        let tree = chat.tree_items(&HashSet::new());
+        let messages: Vec<ExportedMessage> = match full_tree {
+            true => {
+                // Replies keep their parent's depth, only alternatives indent,
+                // so a linear chat doesn't turn into a staircase.
+                let mut parents: Vec<(usize, usize)> = vec![];
+                tree.iter()
+                    .map(|item| {
+                        parents.truncate(item.path.len() - 1);
+                        let depth = match parents.last() {
+                            Some((depth, nb_childs)) => depth + usize::from(*nb_childs > 1),
+                            None => 0,
+                        };
+                        parents.push((depth, item.nb_childs));
+                        ExportedMessage {
+                            label: Some(
+                                item.path
+                                    .iter()
+                                    .map(|i| (i + 1).to_string())
+                                    .collect::<Vec<String>>()
+                                    .join("."),
+                            ),
+                            depth,
+                            active: item.active,
+                            ..ExportedMessage::new(item.message)
+                        }
+                    })
+                    .collect()
+            }
+            false => branch.iter().map(ExportedMessage::new).collect(),
+        };
+        let title = format!("{}'s chat with {}", user.name(), char.name());
+        Ok(match format {
+            ExportFormat::Markdown => markdown::export(&title, &messages),
+            ExportFormat::Html => html::export(&title, &messages)?,
+            ExportFormat::Text => text::export(&title, &messages),
+            ExportFormat::Dot => dot::export(&title, &messages),
+            ExportFormat::Json => json::export(chat, char, user)?,
+        })
+    }
+
+    /// Writes an export of `session` to the exports directory and returns its path.
+    pub fn export_to_file(
+        chat: &Chat,
+        char: &Persona,
+        user: &Persona,
+        session: &Path,
+        format: ExportFormat,
+        full_tree: bool,
+    ) -> Result<PathBuf> {
+        let stem = session
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("chat");
+        let path = Self::exports_path().join(format!(
+            "{}_{}{}.{}",
+            files::file_name(char.name()),
+            stem,
+            if full_tree && !format.is_tree() {
+                "_tree"
+            } else {
+                ""
+            },
+            format.extension()
+        ));
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, Self::export(chat, char, user, format, full_tree)?)?;
+        trace!("Exported {}", path.display());
+        Ok(path)
+    }
+
+    /// Writes `char` as a card to the exports directory and returns its path.
+    pub fn export_card(char: &Persona, format: CardFormat) -> Result<PathBuf> {
+        let path = Self::exports_path().join(format!(
+            "{}.{}",
+            files::file_name(char.name()),
+            format.extension()
+        ));
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, format.export(char)?)?;
+        trace!("Exported {}", path.display());
+        Ok(path)
+    }
+
+    fn exports_path() -> PathBuf {
+        dirs::download_dir()
+            .or_else(dirs::cache_dir)
+            .map(|mut path| {
+                path.push("fullmoon");
+                path.push("exports");
+                path
+            })
+            .unwrap()
+    }
+
+    /// Headless entry point: `fullmoon export <md|html|txt|dot|json> <session.json> [--tree] [--out FILE]`.
+    /// The character is the one the session is saved under, the user the one locked to the chat
+    /// or the character, else the selected one.
+    pub fn cli(args: &[String]) -> Result<()> {
+        if args.first().is_some_and(|a| a == "card") {
+            return Self::card_cli(&args[1..]);
+        }
+        let usage =
+            "usage: fullmoon export <md|html|txt|dot|json> <session.json> [--tree] [--out FILE]";
+        let format = args
+            .first()
+            .and_then(|f| ExportFormat::from_extension(f))
+            .ok_or(anyhow!(usage))?;
+        let session = PathBuf::from(args.get(1).ok_or(anyhow!(usage))?);
+        let full_tree = args.iter().any(|a| a == "--tree");
+        let out = args
+            .iter()
+            .position(|a| a == "--out")
+            .map(|i| args.get(i + 1).map(PathBuf::from).ok_or(anyhow!(usage)))
+            .transpose()?;
+
+        let char_dir = session
+            .parent()
+            .and_then(|p| p.file_name())
+            .and_then(|n| n.to_str())
+            .unwrap_or_default();
+        let char = PersonaLoader::load_from_cache(Subdir::Chars)
+            .into_iter()
+            .find(|p| files::file_name(p.name()) == char_dir)
+            .unwrap_or_else(Persona::default_char);
+        let selected = PersonaLoader::load_selected_user(Settings::load().user());
+        let mut chat = Chat::load(&session, &char, &selected)?;
+        let lock = chat.user_lock().map(str::to_string).or(char.user_lock());
+        let user = PersonaLoader::load_locked_user(lock.as_deref(), &selected);
+        chat.set_owners(&char, &user);
+
+        let export = Self::export(&chat, &char, &user, format, full_tree)?;
+        match out {
+            Some(out) => fs::write(out, export)?,
+            None => print!("{export}"),
+        }
+        Ok(())
+    }
+
+    /// `fullmoon export card <name> [--charx] [--out FILE]`, prints the path of the written card.
+    fn card_cli(args: &[String]) -> Result<()> {
+        let usage = "usage: fullmoon export card <name> [--charx] [--out FILE]";
+        let format = match args.iter().any(|a| a == "--charx") {
+            true => CardFormat::CharX,
+            false => CardFormat::Png,
+        };
+        let name = args.first().ok_or(anyhow!(usage))?;
+        let char = PersonaLoader::load_from_cache(Subdir::Chars)
+            .into_iter()
+            .find(|p| p.name().eq_ignore_ascii_case(name))
+            .ok_or(anyhow!("Character {name} not found"))?;
+        let path = match args.iter().position(|a| a == "--out") {
+            Some(i) => {
+                let out = PathBuf::from(args.get(i + 1).ok_or(anyhow!(usage))?);
+                fs::write(&out, format.export(&char)?)?;
+                out
+            }
+            None => Self::export_card(&char, format)?,
+        };
+        println!("{}", path.display());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ExportFormat, Exporter};
+    use crate::{chat_page::chat::Chat, message::Message, persona::Persona};
+
+    /// A question answered twice, the second answer selected, then narration
+    /// and a system note left out of the prompt.
+    fn chat() -> (Chat, Persona, Persona) {
+        let (char, user) = (Persona::default_char(), Persona::default_user());
+        let mut chat = Chat::default();
+        chat.push(Message::from_user(
+            user.clone(),
+            "Is 1 < 2 & 3?".to_string(),
+        ));
+        chat.push_alternatives(
+            vec![
+                Message::from_char(char.clone(), "Rejected answer".to_string()),
+                Message::from_char(char.clone(), "*nods* \"Yes.\"".to_string()),
+            ],
+            1,
+        );
+        chat.push(Message::from_narrator(
+            "Night falls.\nStars appear.".to_string(),
+        ));
+        let mut note = Message::from_system("Hidden note".to_string());
+        note.excluded = true;
+        chat.push(note);
+        (chat, char, user)
+    }
+
+    fn export(format: ExportFormat, full_tree: bool) -> String {
+        let (chat, char, user) = chat();
+        Exporter::export(&chat, &char, &user, format, full_tree).unwrap()
+    }
+
+    #[test]
+    fn markdown_follows_the_selected_branch() {
+        let (_, char, user) = chat();
+        let markdown = export(ExportFormat::Markdown, false);
+        assert!(markdown.starts_with(&format!("# {}'s chat with {}\n", user.name(), char.name())));
+        assert!(markdown.contains(&format!("**{}** · ", char.name())));
+        assert!(markdown.contains("*nods* \"Yes.\"\n"));
+        assert!(!markdown.contains("Rejected answer"));
+        assert!(markdown.contains("> Night falls.\n> Stars appear.\n"));
+        assert!(markdown.contains("_(excluded)_"));
+
+        let tree = export(ExportFormat::Markdown, true);
+        assert!(tree.contains("Rejected answer"));
+        assert!(tree.contains(&format!("`1.2` **{}**", char.name())));
+        assert_eq!(tree.matches("_(selected").count(), 4);
+        assert!(tree.contains("_(selected, excluded)_"));
+    }
+
+    #[test]
+    fn text_indents_the_full_tree() {
+        let (_, char, _) = chat();
+        let text = export(ExportFormat::Text, false);
+        assert!(!text.contains("Rejected answer"));
+        assert!(text.contains("Night falls.\nStars appear.\n"));
+        assert!(text.contains("[excluded]"));
+
+        let tree = export(ExportFormat::Text, true);
+        assert!(tree.contains(&format!("\n  [1.1] {} (", char.name())));
+        assert!(tree.contains("\n  Rejected answer\n"));
+        assert!(tree.contains(&format!("\n  [1.2*] {} (", char.name())));
+        assert!(tree.contains("\n  [1.2.1*] Narrator ("));
+    }
+
+    #[test]
+    fn linear_chats_are_not_indented() {
+        let (char, user) = (Persona::default_char(), Persona::default_user());
+        let mut chat = Chat::default();
+        chat.push(Message::from_user(user.clone(), "Hello".to_string()));
+        chat.push(Message::from_char(char.clone(), "Hi".to_string()));
+        chat.push(Message::from_user(user.clone(), "Bye".to_string()));
+
+        let text = Exporter::export(&chat, &char, &user, ExportFormat::Text, true).unwrap();
+        assert!(text.contains("\n[1.1.1*] "));
+        assert!(!text.contains("\n "));
+        let html = Exporter::export(&chat, &char, &user, ExportFormat::Html, true).unwrap();
+        assert!(!html.contains("margin-left: 24px"));
+    }
+
+    #[test]
+    fn html_escapes_and_embeds_avatars() {
+        let html = export(ExportFormat::Html, false);
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("Is 1 &lt; 2 &amp; 3?"));
+        assert!(!html.contains("1 < 2"));
+        assert!(html.contains("src=\"data:image/png;base64,"));
+        assert!(html.contains("class=\"message system excluded\""));
+        assert!(!html.contains("Rejected answer"));
+        assert!(html.contains("Night falls.<br>Stars appear."));
+
+        let tree = export(ExportFormat::Html, true);
+        assert!(tree.contains("Rejected answer"));
+        assert!(tree.contains("class=\"message char active\""));
+        assert!(tree.contains("margin-left: 24px"));
+    }
+}
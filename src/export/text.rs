@@ -0,0 +1,24 @@
+use crate::export::ExportedMessage;
+
+pub fn export(title: &str, messages: &[ExportedMessage]) -> String {
+    let mut out = format!("{title}\n");
+    for message in messages {
+        let indent = "  ".repeat(message.depth);
+        let label = match &message.label {
+            Some(label) => format!("[{label}{}] ", if message.active { "*" } else { "" }),
+            None => String::new(),
+        };
+        out.push_str(&format!(
+            "\n{}{}{} ({}){}:\n",
+            indent,
+            label,
+            message.owner.name(),
+            message.time.format("%Y-%m-%d %H:%M"),
+            if message.excluded { " [excluded]" } else { "" }
+        ));
+        for line in message.text.lines() {
+            out.push_str(&format!("{indent}{line}\n"));
+        }
+    }
+    out
+}
@@ -1,10 +1,13 @@
 use iced::{
     Color, Element, Font, Length,
     font::Style,
-    widget::{span, text::Rich},
+    widget::{
+        span,
+        text::{Rich, Span},
+    },
 };
 use iced_modern_theme::colors::colors;
-use regex::Regex;
+use regex::{Regex, RegexBuilder};
 
 use crate::{AppCommand, settings::Settings};
 
@@ -27,8 +30,9 @@ impl Formater {
         few_linebreaks.trim().to_string()
     }
 
-    pub fn rich_text<'a>(text: &'a str, settings: &'a Settings) -> Element<'a, AppCommand> {
-        let mut spans = vec![];
+    /// Splits `text` into runs of plain, emphasised (`*...*`) and quoted text.
+    pub fn segments(text: &str) -> Vec<(String, StringType)> {
+        let mut segments = vec![];
         let mut current_type = StringType::Normal;
         let mut current_string = String::new();
         let mut push_char_anyway = false;
@@ -63,12 +67,7 @@ impl Formater {
                     if push_char_anyway && push_before {
                         current_string.push(char);
                     }
-                    spans.push(
-                        span(current_string)
-                            .size(settings.font_size())
-                            .color(current_type.clone())
-                            .font(current_type),
-                    );
+                    segments.push((current_string, current_type));
                     current_type = nt;
                     current_string = String::new();
                     if push_char_anyway && !push_before {
@@ -80,18 +79,78 @@ impl Formater {
             push_char_anyway = false;
             push_before = false;
         }
+        segments.push((current_string, current_type));
+        segments
+    }
+
+    /// Dimmed text is used for messages excluded from the prompt, `highlight`
+    /// marks every case insensitive occurrence of a search query.
+    pub fn rich_text<'a>(
+        text: &'a str,
+        dimmed: bool,
+        highlight: Option<&str>,
+        settings: &'a Settings,
+    ) -> Element<'a, AppCommand> {
+        let alpha = if dimmed { 0.4 } else { 1.0 };
+        let highlight = highlight.filter(|h| !h.is_empty()).and_then(|h| {
+            RegexBuilder::new(&regex::escape(h))
+                .case_insensitive(true)
+                .build()
+                .ok()
+        });
+        let mut spans = vec![];
+        for (string, string_type) in Self::segments(text) {
+            Self::push_spans(
+                &mut spans,
+                string,
+                string_type,
+                alpha,
+                highlight.as_ref(),
+                settings,
+            );
+        }
+        Rich::with_spans(spans).width(Length::Shrink).into()
+    }
+
+    fn push_spans(
+        spans: &mut Vec<Span<'_, AppCommand>>,
+        string: String,
+        string_type: StringType,
+        alpha: f32,
+        highlight: Option<&Regex>,
+        settings: &Settings,
+    ) {
+        let color = Color::from(string_type.clone()).scale_alpha(alpha);
+        let mut last = 0;
+        if let Some(highlight) = highlight {
+            for m in highlight.find_iter(&string) {
+                spans.push(
+                    span(string[last..m.start()].to_string())
+                        .size(settings.font_size())
+                        .color(color)
+                        .font(string_type.clone()),
+                );
+                spans.push(
+                    span(m.as_str().to_string())
+                        .size(settings.font_size())
+                        .color(colors::fill::BACKGROUND_DARK)
+                        .background(colors::macos::FIND_HIGHLIGHT)
+                        .font(string_type.clone()),
+                );
+                last = m.end();
+            }
+        }
         spans.push(
-            span(current_string)
+            span(string[last..].to_string())
                 .size(settings.font_size())
-                .color(current_type.clone())
-                .font(current_type),
+                .color(color)
+                .font(string_type),
         );
-        Rich::with_spans(spans).width(Length::Shrink).into()
     }
 }
 
-#[derive(Clone)]
-enum StringType {
+#[derive(Clone, PartialEq)]
+pub enum StringType {
     Normal,
     Strong,
     Quote,
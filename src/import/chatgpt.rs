@@ -0,0 +1,309 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{Result, anyhow};
+use log::error;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::{
+    chat_page::chat::{Chat, MessageNode},
+    import::{Imported, parse_date},
+    message::Message,
+    persona::Persona,
+};
+
+#[derive(Deserialize)]
+struct Conversation {
+    title: Option<String>,
+    mapping: HashMap<String, Node>,
+    current_node: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Node {
+    message: Option<NodeMessage>,
+    parent: Option<String>,
+    #[serde(default)]
+    children: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct NodeMessage {
+    author: Author,
+    content: Value,
+    create_time: Option<Value>,
+}
+
+#[derive(Deserialize)]
+struct Author {
+    role: String,
+}
+
+/// Parses a `conversations.json` export, one chat per conversation, and lists
+/// the conversations that failed.
+pub fn import(data: &str) -> Result<(Vec<Imported>, Vec<String>)> {
+    let conversations: Vec<Value> = serde_json::from_str(data)?;
+    let mut imported: Vec<Imported> = vec![];
+    let mut failed = vec![];
+    for (idx, conversation) in conversations.into_iter().enumerate() {
+        match serde_json::from_value::<Conversation>(conversation)
+            .map_err(anyhow::Error::from)
+            .and_then(import_conversation)
+        {
+            Ok(chat) => imported.push(chat),
+            Err(e) => {
+                error!("conversation {}: {e}", idx + 1);
+                failed.push(format!("conversation {}: {e}", idx + 1));
+            }
+        }
+    }
+    match (imported.is_empty(), failed.is_empty()) {
+        (false, _) => Ok((imported, failed)),
+        (true, true) => Err(anyhow!("No conversations found")),
+        (true, false) => Err(anyhow!(
+            "No conversations imported:\n  {}",
+            failed.join("\n  ")
+        )),
+    }
+}
+
+fn import_conversation(conversation: Conversation) -> Result<Imported> {
+    let mut roots: Vec<&String> = conversation
+        .mapping
+        .iter()
+        .filter(|(_, node)| {
+            node.parent
+                .as_ref()
+                .is_none_or(|p| !conversation.mapping.contains_key(p))
+        })
+        .map(|(id, _)| id)
+        .collect();
+    roots.sort();
+
+    // Ids from the current node up to the root, to restore the selection.
+    let mut skipped = vec![];
+    let mut current_path = HashSet::new();
+    let mut current = conversation.current_node.clone();
+    while let Some(id) = current {
+        current = conversation.mapping.get(&id).and_then(|n| n.parent.clone());
+        if !current_path.insert(id.clone()) {
+            skipped.push(format!("parent cycle at node {id}"));
+            break;
+        }
+    }
+
+    let mut builder = Builder {
+        mapping: &conversation.mapping,
+        current_path: &current_path,
+        visited: HashSet::new(),
+        messages: 0,
+        skipped,
+    };
+    let (childs, selected) = builder.build_level(roots.into_iter().cloned().collect());
+    if builder.messages == 0 {
+        return Err(anyhow!("No messages found"));
+    }
+    Ok(Imported {
+        chat: Chat::from_nodes(childs, selected),
+        char_name: None,
+        title: conversation.title,
+        messages: builder.messages,
+        skipped: builder.skipped,
+    })
+}
+
+struct Builder<'a> {
+    mapping: &'a HashMap<String, Node>,
+    current_path: &'a HashSet<String>,
+    /// Nodes already built, a node met twice closes a cycle.
+    visited: HashSet<String>,
+    messages: usize,
+    skipped: Vec<String>,
+}
+
+impl Builder<'_> {
+    /// Turns sibling ids into nodes. Hidden nodes (empty system prompts, tool
+    /// calls) are dropped and their children lifted into this level.
+    fn build_level(&mut self, ids: Vec<String>) -> (Vec<MessageNode>, usize) {
+        let mut nodes = vec![];
+        let mut selected = 0;
+        for id in ids {
+            let Some(node) = self.mapping.get(&id) else {
+                self.skipped.push(format!("missing node {id}"));
+                continue;
+            };
+            if !self.visited.insert(id.clone()) {
+                self.skipped.push(format!("child cycle at node {id}"));
+                continue;
+            }
+            let on_current_path = self.current_path.contains(&id);
+            match node.message.as_ref().and_then(|m| self.message(&id, m)) {
+                Some(message) => {
+                    let (childs, child_selected) = self.build_level(node.children.clone());
+                    if on_current_path {
+                        selected = nodes.len();
+                    }
+                    nodes.push(MessageNode::with_childs(message, childs, child_selected));
+                }
+                None => {
+                    let (childs, child_selected) = self.build_level(node.children.clone());
+                    if on_current_path {
+                        selected = nodes.len() + child_selected;
+                    }
+                    nodes.extend(childs);
+                }
+            }
+        }
+        (nodes, selected)
+    }
+
+    fn message(&mut self, id: &str, message: &NodeMessage) -> Option<Message> {
+        let text = text(&message.content)?;
+        let time = message.create_time.as_ref().and_then(parse_date);
+        let mut message = match message.author.role.as_str() {
+            "user" => Message::from_user(Persona::default_user(), text),
+            "assistant" => Message::from_char(Persona::default_char(), text),
+            "system" if !text.trim().is_empty() => Message::from_system(text),
+            "system" => return None,
+            role => {
+                self.skipped.push(format!("{role} message {id}"));
+                return None;
+            }
+        };
+        if let Some(time) = time {
+            message.time = time;
+        }
+        self.messages += 1;
+        Some(message)
+    }
+}
+
+fn text(content: &Value) -> Option<String> {
+    if let Some(parts) = content.get("parts").and_then(|p| p.as_array()) {
+        return Some(
+            parts
+                .iter()
+                .filter_map(|p| p.as_str())
+                .collect::<Vec<&str>>()
+                .join("\n"),
+        );
+    }
+    content
+        .get("text")
+        .and_then(|t| t.as_str())
+        .map(|t| t.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{Value, json};
+
+    use super::import;
+    use crate::chat_page::chat::Chat;
+
+    fn node(role: &str, text: &str, parent: Option<&str>, children: &[&str]) -> Value {
+        json!({
+            "message": {
+                "author": {"role": role},
+                "content": {"content_type": "text", "parts": [text]},
+                "create_time": 1700000000.5,
+            },
+            "parent": parent,
+            "children": children,
+        })
+    }
+
+    /// A reply regenerated once, the first version continued through a tool call.
+    fn conversation(current_node: &str) -> Value {
+        json!({
+            "title": format!("Up to {current_node}"),
+            "current_node": current_node,
+            "mapping": {
+                "root": {"message": null, "parent": null, "children": ["sys"]},
+                "sys": node("system", "", Some("root"), &["u1"]),
+                "u1": node("user", "Hi", Some("sys"), &["a1", "a2"]),
+                "a1": node("assistant", "First", Some("u1"), &["t1"]),
+                "t1": node("tool", "search results", Some("a1"), &["a4"]),
+                "a4": node("assistant", "After the search", Some("t1"), &[]),
+                "a2": node("assistant", "Second", Some("u1"), &["u2"]),
+                "u2": node("user", "More", Some("a2"), &["a3"]),
+                "a3": node("assistant", "Done", Some("u2"), &[]),
+            },
+        })
+    }
+
+    fn texts(chat: &Chat) -> Vec<String> {
+        chat.get_current_chat()
+            .into_iter()
+            .map(|m| m.text)
+            .collect()
+    }
+
+    #[test]
+    fn regenerated_replies_become_siblings() {
+        let data = json!([conversation("a3")]).to_string();
+        let (imported, failed) = import(&data).unwrap();
+        assert_eq!(imported.len(), 1);
+        assert!(failed.is_empty());
+        let chat = &imported[0];
+        assert_eq!(chat.messages, 6);
+        assert_eq!(chat.skipped, ["tool message t1"]);
+        let levels = chat.chat.selected_levels();
+        let siblings: Vec<&str> = levels[1].0.iter().map(|m| m.text.as_str()).collect();
+        assert_eq!(siblings, ["First", "Second"]);
+        // Hidden system and tool nodes are dropped, their children lifted.
+        assert_eq!(texts(&chat.chat), ["Hi", "Second", "More", "Done"]);
+    }
+
+    #[test]
+    fn current_node_is_selected() {
+        let data = json!([conversation("a4"), conversation("u2")]).to_string();
+        let (imported, _) = import(&data).unwrap();
+        assert_eq!(
+            texts(&imported[0].chat),
+            ["Hi", "First", "After the search"]
+        );
+        assert_eq!(texts(&imported[1].chat), ["Hi", "Second", "More", "Done"]);
+    }
+
+    #[test]
+    fn failed_conversations_are_reported() {
+        let data = json!([conversation("a3"), {"title": "Broken"}]).to_string();
+        let (imported, failed) = import(&data).unwrap();
+        assert_eq!(imported.len(), 1);
+        assert_eq!(imported[0].skipped, ["tool message t1"]);
+        assert_eq!(failed.len(), 1);
+        assert!(failed[0].starts_with("conversation 2: "));
+
+        let Err(e) = import(&json!([{"title": "Broken"}]).to_string()) else {
+            panic!("a file without conversations imported");
+        };
+        assert!(e.to_string().contains("conversation 1: "));
+    }
+
+    #[test]
+    fn cycles_are_skipped() {
+        let mut parents = conversation("a3");
+        // a3 claims u1 as its parent, u1 claims a3.
+        parents["mapping"]["u1"]["parent"] = json!("a3");
+        parents["mapping"]["a3"]["parent"] = json!("u1");
+        let mut children = conversation("a3");
+        children["mapping"]["a3"]["children"] = json!(["u1"]);
+        let data = json!([parents, children]).to_string();
+
+        let (imported, failed) = import(&data).unwrap();
+        assert!(failed.is_empty());
+        assert!(
+            imported[0]
+                .skipped
+                .iter()
+                .any(|s| s.starts_with("parent cycle at node "))
+        );
+        assert!(
+            imported[1]
+                .skipped
+                .contains(&"child cycle at node u1".to_string())
+        );
+        assert_eq!(texts(&imported[1].chat), ["Hi", "Second", "More", "Done"]);
+    }
+}
@@ -0,0 +1,157 @@
+use std::{
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use log::trace;
+use serde_json::Value;
+
+use crate::{
+    chat_page::{chat::Chat, session::SessionLoader},
+    persona::{
+        Persona,
+        charx::CharX,
+        loader::{PersonaLoader, Subdir},
+    },
+};
+
+mod chatgpt;
+mod sillytavern;
+mod tree;
+
+/// A chat parsed out of a foreign format, before it is matched to a character.
+pub struct Imported {
+    pub chat: Chat,
+    pub char_name: Option<String>,
+    pub title: Option<String>,
+    pub messages: usize,
+    pub skipped: Vec<String>,
+}
+
+pub struct ImportReport {
+    pub char: Persona,
+    pub session: PathBuf,
+    pub title: Option<String>,
+    pub messages: usize,
+    pub skipped: Vec<String>,
+}
+
+impl Display for ImportReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(title) = &self.title {
+            write!(f, "{title}: ")?;
+        }
+        write!(
+            f,
+            "Imported {} messages with {} into {}",
+            self.messages,
+            self.char.name(),
+            self.session.display()
+        )?;
+        for skipped in &self.skipped {
+            write!(f, "\n  skipped {skipped}")?;
+        }
+        Ok(())
+    }
+}
+
+pub struct Importer {}
+
+impl Importer {
+    /// Imports every chat in `path` as new sessions, the format is picked from the extension.
+    /// Also returns the chats of the file that could not be imported.
+    pub fn import(path: &Path) -> Result<(Vec<ImportReport>, Vec<String>)> {
+        let data = fs::read_to_string(path)?;
+        let (imported, failed) = match path.extension().and_then(|e| e.to_str()) {
+            Some("jsonl") => (vec![sillytavern::import(&data)?], vec![]),
+            Some("json") if tree::is_tree(&data) => (vec![tree::import(&data)?], vec![]),
+            Some("json") => chatgpt::import(&data)?,
+            _ => return Err(anyhow!("Unsupported chat file {}", path.display())),
+        };
+        let chars = PersonaLoader::load_from_cache(Subdir::Chars);
+        let reports = imported
+            .into_iter()
+            .map(|imported| Self::save(imported, &chars))
+            .collect::<Result<_>>()?;
+        Ok((reports, failed))
+    }
+
+    fn save(imported: Imported, chars: &[Persona]) -> Result<ImportReport> {
+        let char = Self::find_char(imported.char_name.as_deref(), chars);
+        let session = SessionLoader::new_session_path(&char);
+        imported.chat.save(&session)?;
+        let report = ImportReport {
+            char,
+            session,
+            title: imported.title,
+            messages: imported.messages,
+            skipped: imported.skipped,
+        };
+        trace!("{report}");
+        Ok(report)
+    }
+
+    /// Matches a character by name, falling back on the default one.
+    fn find_char(name: Option<&str>, chars: &[Persona]) -> Persona {
+        name.and_then(|name| {
+            chars
+                .iter()
+                .find(|c| c.name().eq_ignore_ascii_case(name.trim()))
+                .cloned()
+        })
+        .unwrap_or_else(Persona::default_char)
+    }
+
+    /// Headless entry point: `fullmoon import <file>...`, `.charx` files are imported as characters.
+    pub fn cli(args: &[String]) -> Result<()> {
+        if args.is_empty() {
+            return Err(anyhow!("usage: fullmoon import <file>..."));
+        }
+        for arg in args {
+            if CharX::is_charx(Path::new(arg)) {
+                let char = CharX::import(Path::new(arg))?;
+                println!(
+                    "Imported character {} into {}",
+                    char.name(),
+                    char.path().display()
+                );
+                continue;
+            }
+            let (reports, failed) = Self::import(Path::new(arg))?;
+            for report in reports {
+                println!("{report}");
+            }
+            for failed in failed {
+                println!("Not imported {failed}");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads the many date shapes found in exports: RFC 3339 strings, SillyTavern's
+/// "June 11, 2023 1:44am" and unix timestamps in seconds or milliseconds.
+pub fn parse_date(value: &Value) -> Option<DateTime<Local>> {
+    match value {
+        Value::String(date) => DateTime::parse_from_rfc3339(date)
+            .map(|d| d.with_timezone(&Local))
+            .ok()
+            .or_else(|| {
+                NaiveDateTime::parse_from_str(date, "%B %d, %Y %I:%M%P")
+                    .ok()
+                    .and_then(|d| Local.from_local_datetime(&d).single())
+            }),
+        Value::Number(timestamp) => {
+            let timestamp = timestamp.as_f64()?;
+            let millis = match timestamp > 1e11 {
+                true => timestamp as i64,
+                false => (timestamp * 1000.0) as i64,
+            };
+            Local.timestamp_millis_opt(millis).single()
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,167 @@
+use anyhow::{Result, anyhow};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::{
+    chat_page::chat::Chat,
+    import::{Imported, parse_date},
+    message::Message,
+    persona::Persona,
+};
+
+/// First line of a SillyTavern chat file.
+#[derive(Deserialize)]
+struct Header {
+    character_name: Option<String>,
+}
+
+/// Any other line, one message with its swipes.
+#[derive(Deserialize)]
+struct Line {
+    name: Option<String>,
+    #[serde(default)]
+    is_user: bool,
+    /// Hidden from the prompt in SillyTavern.
+    #[serde(default)]
+    is_system: bool,
+    mes: String,
+    swipes: Option<Vec<String>>,
+    swipe_id: Option<usize>,
+    send_date: Option<Value>,
+    extra: Option<Value>,
+}
+
+/// Owners are placeholders, the real personas are attached when the chat is opened.
+pub fn import(data: &str) -> Result<Imported> {
+    let char = Persona::default_char();
+    let user = Persona::default_user();
+    let mut chat = Chat::default();
+    let mut char_name = None;
+    let mut messages = 0;
+    let mut skipped = vec![];
+
+    for (idx, raw) in data.lines().enumerate() {
+        let line_no = idx + 1;
+        if raw.trim().is_empty() {
+            continue;
+        }
+        let value: Value = match serde_json::from_str(raw) {
+            Ok(value) => value,
+            Err(e) => {
+                skipped.push(format!("line {line_no}: {e}"));
+                continue;
+            }
+        };
+        if value.get("mes").is_none() {
+            match (idx, serde_json::from_value::<Header>(value)) {
+                (0, Ok(header)) => char_name = header.character_name,
+                _ => skipped.push(format!("line {line_no}: not a message")),
+            }
+            continue;
+        }
+        let line: Line = match serde_json::from_value(value) {
+            Ok(line) => line,
+            Err(e) => {
+                skipped.push(format!("line {line_no}: {e}"));
+                continue;
+            }
+        };
+        if char_name.is_none() && !line.is_user {
+            char_name = line.name.clone();
+        }
+
+        let narrator = line
+            .extra
+            .as_ref()
+            .and_then(|e| e.get("type"))
+            .is_some_and(|t| t == "narrator");
+        let texts = match line.swipes {
+            Some(swipes) if !swipes.is_empty() => swipes,
+            _ => vec![line.mes],
+        };
+        let time = line.send_date.as_ref().and_then(parse_date);
+        let alternatives: Vec<Message> = texts
+            .into_iter()
+            .map(|text| {
+                let mut message = match (line.is_user, narrator) {
+                    (true, _) => Message::from_user(user.clone(), text),
+                    (false, true) => Message::from_narrator(text),
+                    (false, false) => Message::from_char(char.clone(), text),
+                };
+                message.excluded = line.is_system;
+                if let Some(time) = time {
+                    message.time = time;
+                }
+                message
+            })
+            .collect();
+        messages += alternatives.len();
+        chat.push_alternatives(alternatives, line.swipe_id.unwrap_or_default());
+    }
+
+    if messages == 0 {
+        return Err(anyhow!("No messages found"));
+    }
+    Ok(Imported {
+        chat,
+        char_name,
+        title: None,
+        messages,
+        skipped,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::import;
+    use crate::message::OwnerType;
+
+    const CHAT: &str = r#"{"user_name":"You","character_name":"Seraphina","create_date":"2023-06-11@01h44m"}
+{"name":"You","is_user":true,"is_system":false,"send_date":"June 11, 2023 1:44am","mes":"Where am I?"}
+{"name":"Seraphina","is_user":false,"send_date":1686447900000,"mes":"In my glade.","swipes":["In the forest.","In my glade."],"swipe_id":1}
+not json at all
+
+{"name":"You","is_user":true,"mes":42}
+{"note":"an object that is not a message"}
+{"name":"Narrator","is_user":false,"mes":"The wind rises.","extra":{"type":"narrator"}}
+{"name":"Seraphina","is_user":false,"is_system":true,"mes":"Out of character note"}"#;
+
+    #[test]
+    fn messages_and_swipes_are_imported() {
+        let imported = import(CHAT).unwrap();
+        assert_eq!(imported.char_name.as_deref(), Some("Seraphina"));
+        assert_eq!(imported.messages, 5);
+        let messages = imported.chat.get_current_chat();
+        let texts: Vec<&str> = messages.iter().map(|m| m.text.as_str()).collect();
+        assert_eq!(
+            texts,
+            [
+                "Where am I?",
+                "In my glade.",
+                "The wind rises.",
+                "Out of character note"
+            ]
+        );
+        assert!(messages[2].owner_type == OwnerType::Narrator);
+        assert!(messages[3].excluded);
+        let levels = imported.chat.selected_levels();
+        assert_eq!(levels[1].0.len(), 2);
+        assert_eq!(levels[1].1, 1);
+    }
+
+    #[test]
+    fn bad_lines_are_skipped() {
+        let imported = import(CHAT).unwrap();
+        assert_eq!(imported.skipped.len(), 3);
+        assert!(imported.skipped[0].starts_with("line 4: "));
+        assert!(imported.skipped[1].starts_with("line 6: "));
+        assert_eq!(imported.skipped[2], "line 7: not a message");
+    }
+
+    #[test]
+    fn char_name_falls_back_on_the_first_reply() {
+        let imported = import(r#"{"name":"Aria","is_user":false,"mes":"Hello"}"#).unwrap();
+        assert_eq!(imported.char_name.as_deref(), Some("Aria"));
+        assert!(import("{\"character_name\":\"Aria\"}\nbroken").is_err());
+    }
+}
@@ -0,0 +1,70 @@
+use std::collections::HashSet;
+
+use anyhow::{Result, anyhow};
+use serde_json::Value;
+
+use crate::{export::json::TreeDocument, import::Imported};
+
+/// Tells our own tree exports apart from ChatGPT's `conversations.json`.
+pub fn is_tree(data: &str) -> bool {
+    serde_json::from_str::<Value>(data).is_ok_and(|value| {
+        value
+            .get("format")
+            .is_some_and(|f| f == TreeDocument::FORMAT)
+    })
+}
+
+pub fn import(data: &str) -> Result<Imported> {
+    let document: TreeDocument = serde_json::from_str(data)?;
+    if document.version > TreeDocument::VERSION {
+        return Err(anyhow!(
+            "Unsupported tree version {}, expected {}",
+            document.version,
+            TreeDocument::VERSION
+        ));
+    }
+    let messages = document.chat.tree_items(&HashSet::new()).len();
+    if messages == 0 {
+        return Err(anyhow!("No messages found"));
+    }
+    Ok(Imported {
+        chat: document.chat,
+        char_name: Some(document.char),
+        title: None,
+        messages,
+        skipped: vec![],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::{import, is_tree};
+    use crate::{chat_page::chat::Chat, export::json, message::Message, persona::Persona};
+
+    #[test]
+    fn deep_trees_round_trip() {
+        let (char, user) = (Persona::default_char(), Persona::default_user());
+        let mut chat = Chat::default();
+        for i in 0..300 {
+            chat.push(Message::from_user(user.clone(), format!("Question {i}")));
+            chat.push(Message::from_char(char.clone(), format!("Answer {i}")));
+        }
+        chat.add_alternatives(123, 3, &char);
+
+        let data = json::export(&chat, &char, &user).unwrap();
+        assert!(is_tree(&data));
+        let imported = import(&data).unwrap();
+        assert_eq!(imported.char_name.as_deref(), Some(char.name()));
+        assert_eq!(imported.messages, 603);
+        assert_eq!(
+            imported.chat.tree_items(&HashSet::new()).len(),
+            chat.tree_items(&HashSet::new()).len()
+        );
+        assert_eq!(
+            serde_json::to_value(&imported.chat).unwrap(),
+            serde_json::to_value(&chat).unwrap()
+        );
+    }
+}
@@ -0,0 +1,102 @@
+use std::path::PathBuf;
+
+use iced::{
+    Border, Element,
+    Length::Fill,
+    Theme,
+    widget::{column, container, row, scrollable, text_input},
+};
+use iced_modern_theme::colors::colors;
+
+use crate::{
+    AppCommand,
+    import::ImportReport,
+    settings::Settings,
+    utils::widgets::{bold_text, button, text},
+};
+
+#[derive(Debug, Clone)]
+pub enum ImportCommand {
+    Path(String),
+    Submit,
+}
+
+impl From<ImportCommand> for crate::AppCommand {
+    fn from(import_command: ImportCommand) -> Self {
+        crate::AppCommand::ImportCommand(import_command)
+    }
+}
+
+pub struct ImportPage {
+    path: String,
+    reports: Vec<String>,
+}
+
+impl ImportPage {
+    pub fn new() -> Self {
+        Self {
+            path: String::new(),
+            reports: vec![],
+        }
+    }
+
+    pub fn path(&self) -> PathBuf {
+        PathBuf::from(self.path.trim())
+    }
+
+    pub fn set_path(&mut self, path: String) {
+        self.path = path
+    }
+
+    pub fn add_reports(&mut self, reports: &[ImportReport]) {
+        self.reports
+            .extend(reports.iter().map(|report| report.to_string()));
+    }
+
+    pub fn add_error(&mut self, error: String) {
+        self.reports.push(error)
+    }
+
+    pub fn add_message(&mut self, message: String) {
+        self.reports.push(message)
+    }
+
+    pub fn view<'a>(&'a self, settings: &'a Settings) -> Element<'a, AppCommand> {
+        let mut reports = column![].spacing(10);
+        for report in self.reports.iter().rev() {
+            reports = reports.push(
+                container(text(report, settings))
+                    .padding(10)
+                    .width(Fill)
+                    .style(Self::box_style),
+            );
+        }
+        column![
+            bold_text("Import chats", settings),
+            text(
+                "SillyTavern .jsonl chat files, ChatGPT conversations.json exports, exported JSON trees or .charx characters",
+                settings
+            ),
+            row![
+                text_input("/path/to/chat.jsonl or conversations.json", &self.path)
+                    .size(settings.font_size())
+                    .on_input(|p| ImportCommand::Path(p).into())
+                    .on_paste(|p| ImportCommand::Path(p).into())
+                    .on_submit(ImportCommand::Submit.into()),
+                button("Import", settings).on_press(ImportCommand::Submit.into()),
+            ]
+            .spacing(10),
+            scrollable(reports).height(Fill).width(Fill),
+        ]
+        .padding(10)
+        .spacing(10)
+        .width(Fill)
+        .into()
+    }
+
+    fn box_style(theme: &Theme) -> iced::widget::container::Style {
+        container::rounded_box(theme)
+            .background(colors::fill::SECONDARY_DARK)
+            .border(Border::default().rounded(12))
+    }
+}
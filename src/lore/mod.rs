@@ -0,0 +1,398 @@
+use crate::{
+    lore::report::{EntryReport, KeyMatch, Outcome, Report, Source},
+    persona::card::{CharacterBook, Entry},
+    utils::tokens,
+};
+
+pub mod report;
+pub mod world;
+
+/// Recent messages scanned for keys when neither the book nor the entry say.
+const DEFAULT_SCAN_DEPTH: usize = 2;
+
+/// An entry with the settings of the book it comes from.
+struct Candidate<'a> {
+    entry: &'a Entry,
+    book: &'a str,
+    title: String,
+    scan_depth: usize,
+    recursive: bool,
+}
+
+impl<'a> Candidate<'a> {
+    fn new(book: &'a CharacterBook, idx: usize, entry: &'a Entry) -> Self {
+        let scan_depth = entry
+            .extensions
+            .get("scan_depth")
+            .and_then(|d| d.as_u64())
+            .map(|d| d as usize)
+            .or(book.scan_depth.map(|d| d.max(0) as usize))
+            .unwrap_or(DEFAULT_SCAN_DEPTH);
+        Candidate {
+            entry,
+            book: book.name.as_deref().unwrap_or("Lorebook"),
+            title: entry
+                .comment
+                .clone()
+                .or(entry.name.clone())
+                .filter(|t| !t.is_empty())
+                .or(entry.keys.first().cloned())
+                .unwrap_or_else(|| format!("Entry {}", idx + 1)),
+            scan_depth,
+            recursive: book.recursive_scanning.unwrap_or_default(),
+        }
+    }
+
+    /// The keys, and secondary keys for selective entries, found in the
+    /// scanned messages or in the content of already active entries.
+    fn evaluate(
+        &self,
+        messages: &[&str],
+        activated: &[(&str, &str)],
+    ) -> Result<Vec<KeyMatch>, Outcome> {
+        let mut sources: Vec<(Source, &str)> = messages
+            [messages.len().saturating_sub(self.scan_depth)..]
+            .iter()
+            .rev()
+            .enumerate()
+            .map(|(back, text)| (Source::Message(back), *text))
+            .collect();
+        if self.recursive {
+            sources.extend(
+                activated
+                    .iter()
+                    .map(|(title, content)| (Source::Entry(title.to_string()), *content)),
+            );
+        }
+        let mut matches = self.find_keys(&self.entry.keys, false, &sources);
+        if matches.is_empty() {
+            return Err(Outcome::NoMatch);
+        }
+        let secondary_keys = self.entry.secondary_keys.as_deref().unwrap_or_default();
+        if self.entry.selective.unwrap_or_default() && !secondary_keys.is_empty() {
+            let secondary = self.find_keys(secondary_keys, true, &sources);
+            if secondary.is_empty() {
+                return Err(Outcome::SelectiveMiss);
+            }
+            matches.extend(secondary);
+        }
+        Ok(matches)
+    }
+
+    fn find_keys(
+        &self,
+        keys: &[String],
+        secondary: bool,
+        sources: &[(Source, &str)],
+    ) -> Vec<KeyMatch> {
+        let case_sensitive = self.entry.case_sensitive.unwrap_or_default();
+        let mut matches = vec![];
+        for key in keys.iter().filter(|k| !k.trim().is_empty()) {
+            for (source, text) in sources {
+                let found = match case_sensitive {
+                    true => text.contains(key.as_str()),
+                    false => text.to_lowercase().contains(&key.to_lowercase()),
+                };
+                if found {
+                    matches.push(KeyMatch {
+                        key: key.clone(),
+                        secondary,
+                        source: source.clone(),
+                    });
+                }
+            }
+        }
+        matches
+    }
+}
+
+/// Entries to insert for one generation, and how they were chosen.
+pub struct Activation<'a> {
+    /// In insertion order.
+    pub entries: Vec<&'a Entry>,
+    pub report: Report,
+}
+
+/// The entries of `books` triggered by `messages`, oldest message first.
+/// Entries over the smallest token budget are dropped, lowest priority first.
+pub fn activate<'a>(books: &'a [CharacterBook], messages: &[&str]) -> Activation<'a> {
+    let candidates: Vec<Candidate> = books
+        .iter()
+        .flat_map(|book| {
+            book.entries
+                .iter()
+                .enumerate()
+                .map(move |(i, e)| Candidate::new(book, i, e))
+        })
+        .collect();
+    let mut reports: Vec<EntryReport> = candidates
+        .iter()
+        .map(|c| {
+            let constant = c.entry.enabled && c.entry.constant.unwrap_or_default();
+            EntryReport {
+                book: c.book.to_string(),
+                title: c.title.clone(),
+                outcome: match (c.entry.enabled, constant) {
+                    (false, _) => Outcome::Disabled,
+                    (true, true) => Outcome::Inserted,
+                    (true, false) => Outcome::NoMatch,
+                },
+                constant,
+                step: constant.then_some(0),
+                matches: vec![],
+                tokens: tokens::estimate(&c.entry.content),
+            }
+        })
+        .collect();
+    let mut active: Vec<bool> = reports.iter().map(|r| r.constant).collect();
+
+    // Recursive books also scan the content of active entries, until
+    // nothing new activates.
+    let mut steps = 0;
+    loop {
+        let activated: Vec<(&str, &str)> = candidates
+            .iter()
+            .zip(&active)
+            .filter(|(_, active)| **active)
+            .map(|(c, _)| (c.title.as_str(), c.entry.content.as_str()))
+            .collect();
+        let mut new = vec![];
+        for (i, candidate) in candidates.iter().enumerate() {
+            if !candidate.entry.enabled || active[i] {
+                continue;
+            }
+            match candidate.evaluate(messages, &activated) {
+                Ok(matches) => new.push((i, matches)),
+                Err(outcome) => reports[i].outcome = outcome,
+            }
+        }
+        if new.is_empty() {
+            break;
+        }
+        for (i, matches) in new {
+            active[i] = true;
+            reports[i].outcome = Outcome::Inserted;
+            reports[i].step = Some(steps);
+            reports[i].matches = matches;
+        }
+        steps += 1;
+    }
+
+    // Disabled entries never activate, but the report tells when their keys
+    // would have triggered them.
+    let activated: Vec<(&str, &str)> = candidates
+        .iter()
+        .zip(&active)
+        .filter(|(_, active)| **active)
+        .map(|(c, _)| (c.title.as_str(), c.entry.content.as_str()))
+        .collect();
+    for (i, candidate) in candidates.iter().enumerate() {
+        if candidate.entry.enabled {
+            continue;
+        }
+        match candidate.evaluate(messages, &activated) {
+            Ok(matches) => reports[i].matches = matches,
+            Err(Outcome::NoMatch) => reports[i].outcome = Outcome::NoMatch,
+            Err(_) => {}
+        }
+    }
+
+    let budget = books
+        .iter()
+        .filter_map(|b| b.token_budget)
+        .filter(|b| *b > 0)
+        .min()
+        .map(|b| b as usize);
+    let mut inserted: Vec<usize> = (0..candidates.len()).filter(|i| active[*i]).collect();
+    if let Some(budget) = budget {
+        inserted.sort_by_key(|i| -candidates[*i].entry.priority.unwrap_or_default());
+        let mut spent = 0;
+        inserted.retain(|i| {
+            let cost = reports[*i].tokens;
+            if cost > budget {
+                reports[*i].outcome = Outcome::BudgetOverflow;
+                return false;
+            }
+            if spent + cost > budget {
+                reports[*i].outcome = Outcome::PriorityDrop;
+                return false;
+            }
+            spent += cost;
+            true
+        });
+    }
+    inserted.sort_by_key(|i| (candidates[*i].entry.insertion_order, *i));
+    let tokens = inserted.iter().map(|i| reports[*i].tokens).sum();
+    let scanned = reports.len();
+    // Entries no key reached aren't kept, big books would bloat every message.
+    reports.retain(|r| r.outcome != Outcome::NoMatch);
+    Activation {
+        entries: inserted.iter().map(|i| candidates[*i].entry).collect(),
+        report: Report {
+            entries: reports,
+            scanned,
+            steps,
+            budget,
+            tokens,
+        },
+    }
+}
+
+/// The character's system prompt with `before_char` entries ahead of it
+/// and the others after it.
+pub fn system_prompt(char_prompt: &str, entries: &[&Entry], char: &str, user: &str) -> String {
+    let (before, after): (Vec<&Entry>, Vec<&Entry>) = entries
+        .iter()
+        .partition(|e| e.position.as_deref() == Some("before_char"));
+    before
+        .iter()
+        .map(|e| e.content.as_str())
+        .chain([char_prompt])
+        .chain(after.iter().map(|e| e.content.as_str()))
+        .filter(|s| !s.trim().is_empty())
+        .collect::<Vec<&str>>()
+        .join("\n")
+        .replace("{{char}}", char)
+        .replace("{{user}}", user)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{activate, system_prompt};
+    use crate::lore::report::{KeyMatch, Outcome, Source};
+    use crate::persona::card::{CharacterBook, Entry};
+
+    fn entry(keys: &[&str], content: &str) -> Entry {
+        serde_json::from_value(serde_json::json!({
+            "keys": keys,
+            "content": content,
+            "extensions": {},
+            "enabled": true,
+            "insertion_order": 0,
+        }))
+        .unwrap()
+    }
+
+    fn book(entries: Vec<Entry>) -> CharacterBook {
+        serde_json::from_value(serde_json::json!({
+            "extensions": {},
+            "entries": entries,
+        }))
+        .unwrap()
+    }
+
+    fn contents(entries: Vec<&Entry>) -> Vec<&str> {
+        entries.iter().map(|e| e.content.as_str()).collect()
+    }
+
+    #[test]
+    fn keys_match_recent_messages_only() {
+        let mut selective = entry(&["castle"], "The moat is deep.");
+        selective.selective = Some(true);
+        selective.secondary_keys = Some(vec!["swim".to_string()]);
+        let mut constant = entry(&[], "Magic is real.");
+        constant.constant = Some(true);
+        constant.insertion_order = -1;
+        let books = [book(vec![
+            entry(&["Dragon"], "Dragons hoard gold."),
+            entry(&["lute"], "The lute is cursed."),
+            selective,
+            constant,
+        ])];
+
+        let messages = ["she plays the lute", "a DRAGON!", "to the castle"];
+        assert_eq!(
+            contents(activate(&books, &messages).entries),
+            ["Magic is real.", "Dragons hoard gold."]
+        );
+        let messages = ["we swim", "to the castle"];
+        assert_eq!(
+            contents(activate(&books, &messages).entries),
+            ["Magic is real.", "The moat is deep."]
+        );
+    }
+
+    #[test]
+    fn recursion_follows_entry_content() {
+        let mut books = [
+            book(vec![
+                entry(&["king"], "The king lives in the castle."),
+                entry(&["castle"], "The castle has a moat."),
+            ]),
+            book(vec![entry(&["moat"], "Eels live in the moat.")]),
+        ];
+        assert_eq!(activate(&books, &["the king"]).entries.len(), 1);
+        books[0].recursive_scanning = Some(true);
+        assert_eq!(activate(&books, &["the king"]).entries.len(), 2);
+        books[1].recursive_scanning = Some(true);
+        assert_eq!(activate(&books, &["the king"]).entries.len(), 3);
+    }
+
+    #[test]
+    fn budget_drops_low_priority_entries() {
+        let mut low = entry(&["a"], "Low priority, and long enough to count.");
+        low.priority = Some(1);
+        let mut high = entry(&["a"], "High priority and long as well.");
+        high.priority = Some(5);
+        high.position = Some("before_char".to_string());
+        let mut books = [book(vec![low, high])];
+        books[0].token_budget = Some(10);
+
+        let entries = activate(&books, &["a"]).entries;
+        assert_eq!(
+            contents(entries.clone()),
+            ["High priority and long as well."]
+        );
+        assert_eq!(
+            system_prompt("You are {{char}}.", &entries, "Aria", "Bob"),
+            "High priority and long as well.\nYou are Aria."
+        );
+    }
+
+    #[test]
+    fn report_explains_every_entry() {
+        let mut disabled = entry(&["king"], "Unused.");
+        disabled.enabled = false;
+        let mut selective = entry(&["king"], "The queen is away.");
+        selective.selective = Some(true);
+        selective.secondary_keys = Some(vec!["queen".to_string()]);
+        let mut books = [book(vec![
+            entry(&["king"], "The king lives in the castle."),
+            entry(&["castle"], "The castle has a moat."),
+            disabled,
+            selective,
+            entry(&["dragon"], "Dragons."),
+        ])];
+        books[0].recursive_scanning = Some(true);
+        books[0].entries[1].comment = Some("Castle".to_string());
+
+        let report = activate(&books, &["The King rides", "home"]).report;
+        let outcomes: Vec<&Outcome> = report.entries.iter().map(|e| &e.outcome).collect();
+        assert_eq!(
+            outcomes,
+            [
+                &Outcome::Inserted,
+                &Outcome::Inserted,
+                &Outcome::Disabled,
+                &Outcome::SelectiveMiss
+            ]
+        );
+        assert_eq!(report.scanned, 5);
+        assert_eq!(report.entries[2].matches[0].key, "king");
+        assert_eq!(
+            report.entries[0].matches,
+            [KeyMatch {
+                key: "king".to_string(),
+                secondary: false,
+                source: Source::Message(1)
+            }]
+        );
+        assert_eq!(report.entries[1].step, Some(1));
+        assert_eq!(
+            report.entries[1].matches[0].source,
+            Source::Entry("king".to_string())
+        );
+        assert_eq!(report.steps, 2);
+        assert_eq!(report.tokens, 8 + 6);
+    }
+}
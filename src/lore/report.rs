@@ -0,0 +1,141 @@
+use std::fmt::Display;
+
+use iced::{Element, widget::Column};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    AppCommand,
+    settings::Settings,
+    utils::widgets::{bold_text, text},
+};
+
+/// Why an entry was or wasn't inserted.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Outcome {
+    Inserted,
+    Disabled,
+    /// No key in the scanned messages.
+    NoMatch,
+    /// A key matched but none of the secondary keys.
+    SelectiveMiss,
+    /// Bigger than the whole token budget.
+    BudgetOverflow,
+    /// Left out for higher priority entries once the budget ran out.
+    PriorityDrop,
+}
+
+impl Display for Outcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Outcome::Inserted => "inserted",
+            Outcome::Disabled => "disabled",
+            Outcome::NoMatch => "no key matched",
+            Outcome::SelectiveMiss => "no secondary key matched",
+            Outcome::BudgetOverflow => "larger than the token budget",
+            Outcome::PriorityDrop => "dropped for higher priority entries",
+        })
+    }
+}
+
+/// Where a key was found.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Source {
+    /// How many messages back, 0 being the latest.
+    Message(usize),
+    /// The content of an entry activated earlier, by title.
+    Entry(String),
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Source::Message(0) => write!(f, "the latest message"),
+            Source::Message(1) => write!(f, "the message before"),
+            Source::Message(back) => write!(f, "the message {back} back"),
+            Source::Entry(title) => write!(f, "entry {title}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KeyMatch {
+    pub key: String,
+    pub secondary: bool,
+    pub source: Source,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EntryReport {
+    pub book: String,
+    pub title: String,
+    pub outcome: Outcome,
+    pub constant: bool,
+    /// Scanning pass the entry activated in, later passes are recursion.
+    pub step: Option<usize>,
+    pub matches: Vec<KeyMatch>,
+    pub tokens: usize,
+}
+
+/// How the lore of one generation came together, kept with the generated message.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Report {
+    /// The entries a key reached, disabled and unmatched ones are only counted.
+    pub entries: Vec<EntryReport>,
+    /// Every entry of the scanned books.
+    #[serde(default)]
+    pub scanned: usize,
+    /// Scanning passes that activated entries, more than one with recursion.
+    pub steps: usize,
+    pub budget: Option<usize>,
+    /// Estimated tokens of the inserted entries.
+    pub tokens: usize,
+}
+
+impl Report {
+    pub fn view<'a>(&'a self, settings: &'a Settings) -> Element<'a, AppCommand> {
+        let inserted = self
+            .entries
+            .iter()
+            .filter(|e| e.outcome == Outcome::Inserted)
+            .count();
+        let mut column = Column::new().spacing(4).push(bold_text(
+            format!(
+                "Lore: {inserted} of {} entries, ~{} tokens{}, {} scanning passes",
+                self.scanned,
+                self.tokens,
+                match self.budget {
+                    Some(budget) => format!(" of {budget}"),
+                    None => String::new(),
+                },
+                self.steps
+            ),
+            settings,
+        ));
+        for entry in &self.entries {
+            let how = match (entry.constant, entry.step) {
+                (true, _) => ", constant".to_string(),
+                (false, Some(0)) | (false, None) => String::new(),
+                (false, Some(step)) => format!(", recursion step {step}"),
+            };
+            column = column.push(text(
+                format!(
+                    "{} / {}: {}{how}, ~{} tokens",
+                    entry.book, entry.title, entry.outcome, entry.tokens
+                ),
+                settings,
+            ));
+            for key in &entry.matches {
+                column = column.push(text(
+                    format!(
+                        "    {}key \"{}\" in {}",
+                        if key.secondary { "secondary " } else { "" },
+                        key.key,
+                        key.source
+                    ),
+                    settings,
+                ));
+            }
+        }
+        column.into()
+    }
+}
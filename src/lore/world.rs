@@ -0,0 +1,204 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Result, anyhow};
+use log::{error, trace};
+use serde_json::{Map, Value, json};
+
+use crate::{
+    persona::{card::CharacterBook, lenient},
+    utils::files::{self, write_atomic},
+};
+
+/// SillyTavern entry fields with a V2 equivalent, the others are kept in
+/// the entry's extensions.
+const SILLYTAVERN_FIELDS: [&str; 10] = [
+    "uid",
+    "key",
+    "keysecondary",
+    "comment",
+    "content",
+    "constant",
+    "selective",
+    "order",
+    "disable",
+    "caseSensitive",
+];
+
+/// A lorebook of its own, shared by any character or chat it is attached to.
+#[derive(Debug, Clone)]
+pub struct World {
+    /// The file name without extension, how worlds are referred to.
+    pub name: String,
+    pub book: CharacterBook,
+}
+
+/// World books live in the `worlds` cache directory as V2 lorebook JSON.
+pub struct WorldLoader {}
+
+impl WorldLoader {
+    /// Every world book, by name.
+    pub fn load_from_cache() -> Vec<World> {
+        let mut worlds: Vec<World> = match fs::read_dir(Self::worlds_path()) {
+            Ok(entries) => entries
+                .flatten()
+                .map(|e| e.path())
+                .filter(|p| p.extension().is_some_and(|e| e == "json"))
+                .filter_map(|p| Self::load_file(&p).inspect_err(|e| error!("{e}")).ok())
+                .collect(),
+            Err(_) => vec![],
+        };
+        worlds.sort_by(|a, b| a.name.cmp(&b.name));
+        worlds
+    }
+
+    pub fn load(name: &str) -> Result<World> {
+        Self::load_file(&Self::worlds_path().join(format!("{name}.json")))
+    }
+
+    /// Copies a SillyTavern world or a V2 lorebook into the worlds directory.
+    pub fn import(path: &Path) -> Result<World> {
+        let mut book = Self::parse(&fs::read_to_string(path)?)?;
+        let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("world");
+        let name = files::file_name(
+            book.name
+                .as_deref()
+                .filter(|n| !n.trim().is_empty())
+                .unwrap_or(stem),
+        );
+        book.name.get_or_insert(name.clone());
+
+        let root = Self::worlds_path();
+        fs::create_dir_all(&root)?;
+        let mut unique = name.clone();
+        let mut n = 1;
+        while root.join(format!("{unique}.json")).exists() {
+            n += 1;
+            unique = format!("{name}-{n}");
+        }
+        let world = World { name: unique, book };
+        let target = root.join(format!("{}.json", world.name));
+        write_atomic(
+            &target,
+            serde_json::to_string_pretty(&world.book)?.as_bytes(),
+        )?;
+        trace!("Imported {} into {}", path.display(), target.display());
+        Ok(world)
+    }
+
+    fn load_file(path: &Path) -> Result<World> {
+        let name = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .ok_or(anyhow!("Invalid world file {}", path.display()))?
+            .to_string();
+        let book = Self::parse(&fs::read_to_string(path)?)?;
+        Ok(World { name, book })
+    }
+
+    /// Reads a V2 lorebook, or a SillyTavern world with its entries keyed by uid.
+    fn parse(data: &str) -> Result<CharacterBook> {
+        let mut value: Value = serde_json::from_str(data)?;
+        if value.get("entries").is_some_and(Value::is_object) {
+            value = Self::from_sillytavern(value);
+        }
+        let (value, coercions) = lenient::book(value)?;
+        let book: CharacterBook = serde_json::from_value(value)?;
+        lenient::log(
+            &format!("World {}", book.name.as_deref().unwrap_or_default()),
+            &coercions,
+        );
+        Ok(book)
+    }
+
+    fn from_sillytavern(world: Value) -> Value {
+        let mut entries: Vec<Map<String, Value>> = world["entries"]
+            .as_object()
+            .map(|entries| {
+                entries
+                    .values()
+                    .filter_map(|e| e.as_object().cloned())
+                    .collect()
+            })
+            .unwrap_or_default();
+        entries.sort_by_key(|e| e.get("uid").and_then(Value::as_i64));
+        let entries: Vec<Value> = entries
+            .into_iter()
+            .map(|entry| {
+                let mut extensions: Map<String, Value> = entry
+                    .iter()
+                    .filter(|(k, _)| !SILLYTAVERN_FIELDS.contains(&k.as_str()))
+                    .map(|(k, v)| (k.clone(), v.clone()))
+                    .collect();
+                if let Some(depth) = entry.get("scanDepth").filter(|d| d.is_u64()) {
+                    extensions.insert("scan_depth".to_string(), depth.clone());
+                }
+                json!({
+                    "id": entry.get("uid"),
+                    "keys": entry.get("key"),
+                    "secondary_keys": entry.get("keysecondary"),
+                    "comment": entry.get("comment"),
+                    "content": entry.get("content"),
+                    "constant": entry.get("constant"),
+                    "selective": entry.get("selective"),
+                    "insertion_order": entry.get("order"),
+                    "enabled": !entry.get("disable").and_then(Value::as_bool).unwrap_or_default(),
+                    "case_sensitive": entry.get("caseSensitive"),
+                    "position": match entry.get("position").and_then(Value::as_i64) {
+                        Some(0) => "before_char",
+                        _ => "after_char",
+                    },
+                    "extensions": extensions,
+                })
+            })
+            .collect();
+        json!({
+            "name": world.get("name"),
+            "extensions": {},
+            "entries": entries,
+        })
+    }
+
+    fn worlds_path() -> PathBuf {
+        dirs::cache_dir()
+            .map(|mut path| {
+                path.push("fullmoon");
+                path.push("worlds");
+                path
+            })
+            .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WorldLoader;
+
+    #[test]
+    fn sillytavern_world_becomes_a_lorebook() {
+        let book = WorldLoader::parse(
+            r#"{"entries": {
+                "1": {"uid": 1, "key": ["moat"], "keysecondary": [], "comment": "Moat",
+                      "content": "Eels.", "constant": false, "selective": true, "order": 50,
+                      "position": 1, "disable": true, "caseSensitive": null, "probability": 80},
+                "0": {"uid": 0, "key": ["castle"], "content": "Old.", "order": 100,
+                      "position": 0, "scanDepth": 4}
+            }}"#,
+        )
+        .unwrap();
+        assert_eq!(book.entries.len(), 2);
+        let (castle, moat) = (&book.entries[0], &book.entries[1]);
+        assert_eq!(castle.keys, ["castle"]);
+        assert_eq!(castle.position.as_deref(), Some("before_char"));
+        assert_eq!(castle.extensions["scan_depth"], 4);
+        assert!(castle.enabled);
+        assert_eq!(moat.id, Some(1));
+        assert_eq!(moat.insertion_order, 50);
+        assert_eq!(moat.comment.as_deref(), Some("Moat"));
+        assert!(!moat.enabled);
+        assert_eq!(moat.case_sensitive, None);
+        assert_eq!(moat.extensions["probability"], 80);
+    }
+}
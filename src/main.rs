@@ -3,27 +3,50 @@ use std::time::Duration;
 use iced::{
     Border, Element,
     Length::{self, Fill},
-    Task, Theme,
+    Subscription, Task, Theme, keyboard,
     widget::{Row, Stack, column, container, row},
 };
 use iced_modern_theme::Modern;
-use log::trace;
+use log::{error, trace};
 use tokio::time::sleep;
 
 use crate::{
+    char_editor_page::{CharEditorCommand, CharEditorPage},
     char_selector_page::CharSelectorPage,
     chat_page::{ChatCommand, ChatPage},
+    export::{CardFormat, Exporter},
+    import::Importer,
+    import_page::{ImportCommand, ImportPage},
+    persona::{
+        Persona,
+        charx::CharX,
+        loader::{PersonaLoader, Subdir},
+    },
+    search_page::{SearchCommand, SearchPage},
     settings::{Settings, SettingsChange},
-    utils::widgets::{button, text},
+    user_page::{UserCommand, UserPage},
+    utils::{
+        files,
+        widgets::{button, text},
+    },
+    world_page::{WorldCommand, WorldPage},
 };
 
+mod char_editor_page;
 mod char_selector_page;
 mod chat_page;
+mod export;
 mod formater;
+mod import;
+mod import_page;
+mod lore;
 mod message;
 mod persona;
+mod search_page;
 mod settings;
+mod user_page;
 mod utils;
+mod world_page;
 
 pub fn main() -> iced::Result {
     env_logger::Builder::new()
@@ -32,14 +55,35 @@ pub fn main() -> iced::Result {
         .filter_module("llm", log::LevelFilter::Trace)
         .init();
 
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let headless = match args.first().map(|a| a.as_str()) {
+        Some("export") => Some(export::Exporter::cli(&args[1..])),
+        Some("import") => Some(import::Importer::cli(&args[1..])),
+        Some("dataset") => Some(export::dataset::Dataset::cli(&args[1..])),
+        _ => None,
+    };
+    if let Some(result) = headless {
+        if let Err(e) = result {
+            error!("{e}");
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
     iced::application("FullMoon", App::update, App::view)
         .theme(App::theme)
+        .subscription(App::subscription)
         .run_with(|| (App::new(), iced::Task::none()))
 }
 
 struct App {
     chat_page: ChatPage,
+    user_page: Option<UserPage>,
     char_selector_page: Option<CharSelectorPage>,
+    char_editor_page: Option<CharEditorPage>,
+    search_page: Option<SearchPage>,
+    import_page: Option<ImportPage>,
+    world_page: Option<WorldPage>,
     settings: Settings,
     show_settings: bool,
     error: Option<String>,
@@ -49,8 +93,24 @@ struct App {
 enum AppCommand {
     ChatCommand(ChatCommand),
 
+    ToggleUsers,
+    UserCommand(UserCommand),
+
     ToggleChars,
     SelectedChar(usize),
+    ExportChar(usize, CardFormat),
+    EditChar(usize),
+    CharEditorCommand(CharEditorCommand),
+
+    ToggleSearch,
+    SearchCommand(SearchCommand),
+    OpenSearchResult(usize),
+
+    ToggleImport,
+    ImportCommand(ImportCommand),
+
+    ToggleWorlds,
+    WorldCommand(WorldCommand),
 
     ToggleSettings,
     SettignsCommand(SettingsChange),
@@ -61,10 +121,16 @@ enum AppCommand {
 
 impl App {
     fn new() -> Self {
+        let settings = Settings::load();
         App {
-            chat_page: ChatPage::try_load(),
+            chat_page: ChatPage::try_load(&settings),
+            user_page: None,
             char_selector_page: None,
-            settings: Settings::load(),
+            char_editor_page: None,
+            search_page: None,
+            import_page: None,
+            world_page: None,
+            settings,
             show_settings: false,
             error: None,
         }
@@ -75,6 +141,71 @@ impl App {
             AppCommand::ChatCommand(chat_command) => {
                 return self.chat_page.update(chat_command, &self.settings);
             }
+            AppCommand::ToggleUsers => {
+                self.user_page = match self.user_page {
+                    None => {
+                        trace!("Opening user page");
+                        Some(UserPage::new())
+                    }
+                    Some(_) => {
+                        trace!("Closing user page");
+                        None
+                    }
+                };
+            }
+            AppCommand::UserCommand(user_command) => {
+                if let Some(user_page) = &mut self.user_page {
+                    match user_command {
+                        UserCommand::Select(idx) => {
+                            let user = user_page.get(idx);
+                            trace!("Selected user {}", user.name());
+                            self.chat_page.set_user(user)
+                        }
+                        UserCommand::Save => {
+                            let old_path = user_page.editing().map(|u| u.path().to_path_buf());
+                            match user_page.save() {
+                                Ok(user) => match old_path {
+                                    Some(old_path) => self.chat_page.replace_user(&old_path, user),
+                                    None => self.chat_page.refresh_user(),
+                                },
+                                Err(e) => return Task::done(AppCommand::Error(e.to_string())),
+                            }
+                        }
+                        UserCommand::Delete(idx) => match user_page.delete(idx) {
+                            Ok(user) if user.path() == self.chat_page.selected_user().path() => {
+                                self.chat_page
+                                    .set_user(PersonaLoader::load_most_recent_from_cache(
+                                        Subdir::Users,
+                                    ))
+                            }
+                            Ok(_) => self.chat_page.refresh_user(),
+                            Err(e) => return Task::done(AppCommand::Error(e.to_string())),
+                        },
+                        UserCommand::LockChar(idx, locked) => {
+                            let folder = user_page.user(idx).folder().filter(|_| locked);
+                            match self.chat_page.char().lock_user(folder) {
+                                Ok(char) => {
+                                    if let Some(csp) = &mut self.char_selector_page {
+                                        csp.replace(char.clone());
+                                    }
+                                    self.chat_page.replace_char(char);
+                                }
+                                Err(e) => return Task::done(AppCommand::Error(e.to_string())),
+                            }
+                        }
+                        UserCommand::LockChat(idx, locked) => {
+                            let folder = user_page.user(idx).folder().filter(|_| locked);
+                            self.chat_page.lock_user(folder.map(str::to_string))
+                        }
+                        user_command => user_page.update(user_command),
+                    }
+                    // The selected user may have been picked, moved to a new folder or deleted.
+                    let folder = self.chat_page.selected_user().folder().map(str::to_string);
+                    if self.settings.user() != folder.as_deref() {
+                        self.settings.update(SettingsChange::User(folder));
+                    }
+                }
+            }
             AppCommand::ToggleChars => {
                 self.char_selector_page = match self.char_selector_page {
                     None => {
@@ -83,6 +214,7 @@ impl App {
                     }
                     Some(_) => {
                         trace!("Closing Char selector page");
+                        self.char_editor_page = None;
                         None
                     }
                 };
@@ -94,6 +226,162 @@ impl App {
                     self.chat_page.set_char(char)
                 }
             }
+            AppCommand::ExportChar(char_idx, format) => {
+                if let Some(csp) = &self.char_selector_page {
+                    match Exporter::export_card(csp.char(char_idx), format) {
+                        Ok(path) => trace!("Exported card to {}", path.display()),
+                        Err(e) => return Task::done(AppCommand::Error(e.to_string())),
+                    }
+                }
+            }
+            AppCommand::EditChar(char_idx) => {
+                if let Some(csp) = &self.char_selector_page {
+                    let char = csp.char(char_idx).clone();
+                    trace!("Editing {}", char.name());
+                    self.char_editor_page =
+                        Some(CharEditorPage::new(char, csp.other_names(char_idx)));
+                }
+            }
+            AppCommand::CharEditorCommand(CharEditorCommand::Save) => {
+                if let Some(editor) = &self.char_editor_page {
+                    match editor.save() {
+                        Ok(char) => {
+                            if let Some(csp) = &mut self.char_selector_page {
+                                csp.replace(char.clone());
+                            }
+                            self.chat_page.replace_char(char);
+                            self.char_editor_page = None;
+                        }
+                        Err(e) => return Task::done(AppCommand::Error(e.to_string())),
+                    }
+                }
+            }
+            AppCommand::CharEditorCommand(CharEditorCommand::Cancel) => {
+                trace!("Closing character editor");
+                self.char_editor_page = None;
+            }
+            AppCommand::CharEditorCommand(char_editor_command) => {
+                if let Some(editor) = &mut self.char_editor_page {
+                    editor.update(char_editor_command)
+                }
+            }
+
+            AppCommand::ToggleSearch => {
+                self.search_page = match self.search_page {
+                    None => {
+                        trace!("Opening search page");
+                        Some(SearchPage::new())
+                    }
+                    Some(_) => {
+                        trace!("Closing search page");
+                        None
+                    }
+                };
+            }
+            AppCommand::SearchCommand(search_command) => {
+                if let Some(search_page) = &mut self.search_page {
+                    search_page.update(search_command)
+                }
+            }
+            AppCommand::OpenSearchResult(idx) => {
+                if let Some(search_page) = &self.search_page {
+                    let entry = search_page.get(idx);
+                    let char = PersonaLoader::load_from_cache(Subdir::Chars)
+                        .into_iter()
+                        .find(|p| files::file_name(p.name()) == entry.char_name)
+                        .unwrap_or_else(Persona::default_char);
+                    trace!("Opening {} at {:?}", entry.session.display(), entry.path);
+                    return self.chat_page.open_at(char, entry.session, &entry.path);
+                }
+            }
+
+            AppCommand::ToggleImport => {
+                self.import_page = match self.import_page {
+                    None => {
+                        trace!("Opening import page");
+                        Some(ImportPage::new())
+                    }
+                    Some(_) => {
+                        trace!("Closing import page");
+                        None
+                    }
+                };
+            }
+            AppCommand::ImportCommand(import_command) => {
+                if let Some(import_page) = &mut self.import_page {
+                    match import_command {
+                        ImportCommand::Path(path) => import_page.set_path(path),
+                        ImportCommand::Submit if CharX::is_charx(&import_page.path()) => {
+                            match CharX::import(&import_page.path()) {
+                                Ok(char) => import_page.add_message(format!(
+                                    "Imported character {} into {}",
+                                    char.name(),
+                                    char.path().display()
+                                )),
+                                Err(e) => import_page.add_error(e.to_string()),
+                            }
+                        }
+                        ImportCommand::Submit => match Importer::import(&import_page.path()) {
+                            Ok((reports, failed)) => {
+                                import_page.add_reports(&reports);
+                                for failed in failed {
+                                    import_page.add_error(format!("Not imported {failed}"));
+                                }
+                                if let Some(report) = reports.into_iter().next() {
+                                    return self.chat_page.open_at(
+                                        report.char,
+                                        report.session,
+                                        &[],
+                                    );
+                                }
+                            }
+                            Err(e) => import_page.add_error(e.to_string()),
+                        },
+                    }
+                }
+            }
+
+            AppCommand::ToggleWorlds => {
+                self.world_page = match self.world_page {
+                    None => {
+                        trace!("Opening world page");
+                        Some(WorldPage::new())
+                    }
+                    Some(_) => {
+                        trace!("Closing world page");
+                        None
+                    }
+                };
+            }
+            AppCommand::WorldCommand(world_command) => {
+                if let Some(world_page) = &mut self.world_page {
+                    match world_command {
+                        WorldCommand::Path(path) => world_page.set_path(path),
+                        WorldCommand::Import => world_page.import(),
+                        WorldCommand::Global(world, attached) => {
+                            self.settings.update(SettingsChange::World(world, attached))
+                        }
+                        WorldCommand::Char(world, linked) => {
+                            match self
+                                .chat_page
+                                .char()
+                                .link_world(linked.then_some(world.as_str()))
+                            {
+                                Ok(char) => {
+                                    if let Some(csp) = &mut self.char_selector_page {
+                                        csp.replace(char.clone());
+                                    }
+                                    self.chat_page.replace_char(char);
+                                }
+                                Err(e) => return Task::done(AppCommand::Error(e.to_string())),
+                            }
+                        }
+                        WorldCommand::Chat(world, attached) => {
+                            self.chat_page.set_world(world, attached)
+                        }
+                    }
+                }
+            }
 
             AppCommand::ToggleSettings => {
                 self.show_settings = match self.show_settings {
@@ -119,9 +407,32 @@ impl App {
 
     fn view(&self) -> Element<'_, AppCommand> {
         let mut pages = Row::new().spacing(20);
-        if let Some(char_selector_page) = &self.char_selector_page {
+        if let Some(user_page) = &self.user_page {
+            pages = pages.push(user_page.view(
+                &self.settings,
+                self.chat_page.selected_user(),
+                self.chat_page.char(),
+                self.chat_page.user_lock(),
+            ))
+        }
+        if let Some(char_editor_page) = &self.char_editor_page {
+            pages = pages.push(char_editor_page.view(&self.settings))
+        } else if let Some(char_selector_page) = &self.char_selector_page {
             pages = pages.push(char_selector_page.view(&self.settings))
         }
+        if let Some(search_page) = &self.search_page {
+            pages = pages.push(search_page.view(&self.settings))
+        }
+        if let Some(import_page) = &self.import_page {
+            pages = pages.push(import_page.view(&self.settings))
+        }
+        if let Some(world_page) = &self.world_page {
+            pages = pages.push(world_page.view(
+                &self.settings,
+                self.chat_page.char(),
+                self.chat_page.worlds(),
+            ))
+        }
         if self.show_settings {
             pages = pages.push(self.settings.view())
         }
@@ -130,10 +441,21 @@ impl App {
         let mut stack = Stack::new();
         stack = stack.push(column![
             row![
-                button("User", &self.settings).width(Fill),
+                button("User", &self.settings)
+                    .on_press(AppCommand::ToggleUsers)
+                    .width(Fill),
                 button("Characters", &self.settings)
                     .on_press(AppCommand::ToggleChars)
                     .width(Fill),
+                button("Search", &self.settings)
+                    .on_press(AppCommand::ToggleSearch)
+                    .width(Fill),
+                button("Import", &self.settings)
+                    .on_press(AppCommand::ToggleImport)
+                    .width(Fill),
+                button("Worlds", &self.settings)
+                    .on_press(AppCommand::ToggleWorlds)
+                    .width(Fill),
                 button("Settings", &self.settings)
                     .on_press(AppCommand::ToggleSettings)
                     .width(Fill)
@@ -156,6 +478,10 @@ impl App {
         stack.into()
     }
 
+    fn subscription(&self) -> Subscription<AppCommand> {
+        keyboard::on_key_press(utils::binds::from_global_key_press)
+    }
+
     fn theme(&self) -> Theme {
         Modern::theme(true)
     }
@@ -1,18 +1,58 @@
-use crate::persona::Persona;
+use std::fmt::Display;
+
+use crate::{lore::report::Report, persona::Persona};
+use chrono::{DateTime, Local};
 use iced::widget::text_editor::Content;
 use llm::chat::ChatMessage;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub enum OwnerType {
     User,
     Char,
+    System,
+    Narrator,
+}
+
+impl OwnerType {
+    pub const ALL: [OwnerType; 4] = [
+        OwnerType::User,
+        OwnerType::Char,
+        OwnerType::System,
+        OwnerType::Narrator,
+    ];
 }
 
+impl Display for OwnerType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            OwnerType::User => "User",
+            OwnerType::Char => "Char",
+            OwnerType::System => "System",
+            OwnerType::Narrator => "Narrator",
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct Message {
+    /// Not saved, owners are reattached from the chat personas on load.
+    #[serde(skip, default = "Persona::default_char")]
     pub owner: Persona,
     pub owner_type: OwnerType,
     pub text: String,
+    #[serde(default = "Local::now")]
+    pub time: DateTime<Local>,
+    /// Kept in the chat view but never sent to the model.
+    #[serde(default)]
+    pub excluded: bool,
+    #[serde(skip)]
     pub editing: Option<Content>,
+    /// How the lore was picked, for generated messages.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lore: Option<Report>,
+    #[serde(skip)]
+    pub show_lore: bool,
 }
 
 impl Clone for Message {
@@ -21,7 +61,11 @@ impl Clone for Message {
             owner: self.owner.clone(),
             owner_type: self.owner_type.clone(),
             text: self.text.clone(),
+            time: self.time,
+            excluded: self.excluded,
             editing: None,
+            lore: self.lore.clone(),
+            show_lore: self.show_lore,
         }
     }
 }
@@ -32,7 +76,11 @@ impl Message {
             owner: user,
             owner_type: OwnerType::User,
             text,
+            time: Local::now(),
+            excluded: false,
             editing: None,
+            lore: None,
+            show_lore: false,
         }
     }
 
@@ -41,18 +89,77 @@ impl Message {
             owner: char,
             owner_type: OwnerType::Char,
             text: text.trim().to_string(),
+            time: Local::now(),
+            excluded: false,
+            editing: None,
+            lore: None,
+            show_lore: false,
+        }
+    }
+
+    pub fn from_system(text: String) -> Self {
+        Message {
+            owner: Persona::default_system(),
+            owner_type: OwnerType::System,
+            text: text.trim().to_string(),
+            time: Local::now(),
+            excluded: false,
+            editing: None,
+            lore: None,
+            show_lore: false,
+        }
+    }
+
+    pub fn from_narrator(text: String) -> Self {
+        Message {
+            owner: Persona::default_narrator(),
+            owner_type: OwnerType::Narrator,
+            text: text.trim().to_string(),
+            time: Local::now(),
+            excluded: false,
             editing: None,
+            lore: None,
+            show_lore: false,
         }
     }
 
+    /// Builds a message from the input box, "/sys" and "/nar" prefixes insert
+    /// system and narrator messages instead of a user one.
+    pub fn from_input(user: Persona, text: String) -> Self {
+        if let Some(text) = text.strip_prefix("/sys ") {
+            return Self::from_system(text.to_string());
+        }
+        if let Some(text) = text.strip_prefix("/nar ") {
+            return Self::from_narrator(text.to_string());
+        }
+        Self::from_user(user, text)
+    }
+
     pub fn empty_from_char(char: Persona) -> Self {
         Self::from_char(char, String::new())
     }
 
+    pub fn set_owner(&mut self, char: &Persona, user: &Persona) {
+        match self.owner_type {
+            OwnerType::User => self.owner = user.clone(),
+            OwnerType::Char => self.owner = char.clone(),
+            OwnerType::System => self.owner = Persona::default_system(),
+            OwnerType::Narrator => self.owner = Persona::default_narrator(),
+        }
+    }
+
+    /// The llm crate only knows user and assistant roles, so system and
+    /// narrator messages are sent as tagged user turns.
     pub fn to_chat_message(&self) -> ChatMessage {
         match self.owner_type {
             OwnerType::User => ChatMessage::user().content(&self.text).build(),
             OwnerType::Char => ChatMessage::assistant().content(&self.text).build(),
+            OwnerType::System => ChatMessage::user()
+                .content(format!("[System: {}]", self.text))
+                .build(),
+            OwnerType::Narrator => ChatMessage::user()
+                .content(format!("[Narrator: {}]", self.text))
+                .build(),
         }
     }
 }
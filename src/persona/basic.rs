@@ -3,7 +3,7 @@ use std::rc::Rc;
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 
-use crate::persona::{CharData, Persona};
+use crate::persona::{CharData, Persona, card::Card};
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Basic {
@@ -23,6 +23,10 @@ impl CharData for Basic {
     fn greetings(&self, _: Option<&str>) -> Option<Vec<String>> {
         None
     }
+
+    fn card(&self) -> Card {
+        Card::new(&self.name, &self.description)
+    }
 }
 
 impl Basic {
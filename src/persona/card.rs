@@ -3,7 +3,7 @@ use std::{collections::HashMap, rc::Rc};
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 
-use crate::persona::{CharData, Persona};
+use crate::persona::{CharData, Persona, lenient};
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Card {
@@ -15,11 +15,49 @@ pub struct Card {
 
     /// Container for all character-specific fields and configurations.
     pub data: CharacterData,
+
+    /// Fields unknown to the spec, kept as is.
+    #[serde(flatten)]
+    pub extra: Extensions,
 }
 
 impl Card {
+    pub const SPEC: &str = "chara_card_v2";
+    pub const SPEC_VERSION: &str = "2.0";
+
+    /// A card with only a name and a description, everything else empty.
+    pub fn new(name: &str, description: &str) -> Self {
+        Card {
+            spec: Self::SPEC.to_string(),
+            spec_version: Self::SPEC_VERSION.to_string(),
+            data: CharacterData {
+                name: name.to_string(),
+                description: description.to_string(),
+                personality: String::new(),
+                scenario: String::new(),
+                first_mes: String::new(),
+                mes_example: String::new(),
+                creator_notes: String::new(),
+                system_prompt: String::new(),
+                post_history_instructions: String::new(),
+                alternate_greetings: vec![],
+                tags: vec![],
+                creator: String::new(),
+                character_version: String::new(),
+                extensions: Extensions::new(),
+                character_book: None,
+                extra: Extensions::new(),
+            },
+            extra: Extensions::new(),
+        }
+    }
+
+    /// Loads V2 cards and flat V1 ones, filling in missing or mistyped fields.
     pub fn load_from_json(data: &str) -> Result<Rc<Self>> {
-        Ok(Rc::new(serde_json::from_str(data)?))
+        let (value, coercions) = lenient::v2(serde_json::from_str(data)?)?;
+        let card: Self = serde_json::from_value(value)?;
+        lenient::log(&format!("Card {}", card.data.name), &coercions);
+        Ok(Rc::new(card))
     }
 }
 
@@ -31,12 +69,12 @@ impl CharData for Card {
     fn greetings(&self, partner_name: Option<&str>) -> Option<Vec<String>> {
         let mut greetings = vec![self.data.first_mes.clone()];
         greetings.append(&mut self.data.alternate_greetings.clone());
-        Some(
-            greetings
-                .iter()
-                .map(|g| Persona::replace_names(g, &self.data.name, partner_name))
-                .collect(),
-        )
+        let greetings: Vec<String> = greetings
+            .iter()
+            .filter(|g| !g.trim().is_empty())
+            .map(|g| Persona::replace_names(g, &self.data.name, partner_name))
+            .collect();
+        (!greetings.is_empty()).then_some(greetings)
     }
 
     fn system_prompt(&self, partner_name: Option<&str>) -> String {
@@ -57,6 +95,10 @@ impl CharData for Card {
             partner_name,
         )
     }
+
+    fn card(&self) -> Card {
+        self.clone()
+    }
 }
 
 /// Contains core character properties along with new V2 fields.
@@ -107,6 +149,10 @@ pub struct CharacterData {
     /// Optional character-specific lorebook containing background lore and dynamic entries.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub character_book: Option<CharacterBook>,
+
+    /// Fields unknown to the spec, kept as is.
+    #[serde(flatten)]
+    pub extra: Extensions,
 }
 
 pub type Extensions = HashMap<String, serde_json::Value>;
@@ -163,6 +209,10 @@ pub struct Entry {
     /// Specifies the insertion position of this entry's content relative to the character's main definition.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub position: Option<String>,
+
+    /// Fields unknown to the spec, kept as is.
+    #[serde(flatten)]
+    pub extra: Extensions,
 }
 
 /// Represents a character-specific lorebook attached to a character card.
@@ -193,4 +243,8 @@ pub struct CharacterBook {
 
     /// Array of lore entries that comprise the lorebook.
     pub entries: Vec<Entry>,
+
+    /// Fields unknown to the spec, kept as is.
+    #[serde(flatten)]
+    pub extra: Extensions,
 }
@@ -0,0 +1,661 @@
+use std::{collections::HashMap, rc::Rc};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::persona::{
+    CharData, Persona,
+    card::{Card, CharacterBook, CharacterData, Entry, Extensions},
+    lenient,
+};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CardV3 {
+    /// Identifier for the spec; must be "chara_card_v3".
+    pub spec: String,
+
+    /// Specification version; for Character Card V3, this is "3.0".
+    pub spec_version: String,
+
+    /// Container for all character-specific fields and configurations.
+    pub data: CharacterDataV3,
+
+    /// Fields unknown to the spec, kept as is.
+    #[serde(flatten)]
+    pub extra: Extensions,
+}
+
+impl CardV3 {
+    pub const SPEC: &str = "chara_card_v3";
+    pub const SPEC_VERSION: &str = "3.0";
+
+    /// Loads V3 cards, filling in missing or mistyped fields.
+    pub fn load_from_json(data: &str) -> Result<Rc<Self>> {
+        let (value, coercions) = lenient::v3(serde_json::from_str(data)?)?;
+        let card: Self = serde_json::from_value(value)?;
+        lenient::log(&format!("Card {}", card.data.name), &coercions);
+        Ok(Rc::new(card))
+    }
+
+    /// The name `{{char}}` stands for, the nickname when there is one.
+    fn display_name(&self) -> &str {
+        self.data.nickname.as_deref().unwrap_or(&self.data.name)
+    }
+
+    fn replace_names(&self, s: &str, partner_name: Option<&str>) -> String {
+        Persona::replace_names(s, self.display_name(), partner_name)
+    }
+}
+
+impl CharData for CardV3 {
+    fn name(&self) -> &str {
+        &self.data.name
+    }
+
+    /// Group only greetings are left out, chats have a single character.
+    fn greetings(&self, partner_name: Option<&str>) -> Option<Vec<String>> {
+        let mut greetings = vec![self.data.first_mes.clone()];
+        greetings.append(&mut self.data.alternate_greetings.clone());
+        let greetings: Vec<String> = greetings
+            .iter()
+            .filter(|g| !g.trim().is_empty())
+            .map(|g| self.replace_names(g, partner_name))
+            .collect();
+        (!greetings.is_empty()).then_some(greetings)
+    }
+
+    fn system_prompt(&self, partner_name: Option<&str>) -> String {
+        let data = &self.data;
+        self.replace_names(
+            &[
+                &data.system_prompt,
+                &data.description,
+                &data.scenario,
+                &data.mes_example,
+            ]
+            .iter()
+            .filter(|s| !s.is_empty())
+            .map(|s| s.as_str())
+            .collect::<Vec<&str>>()
+            .join("\n"),
+            partner_name,
+        )
+    }
+
+    fn card(&self) -> Card {
+        self.v2(true)
+    }
+
+    fn card_v3(&self) -> CardV3 {
+        self.clone()
+    }
+
+    fn spec(&self) -> &str {
+        &self.spec
+    }
+}
+
+impl CardV3 {
+    /// The card as V2. Lorebook decorators are applied for V3 cards, and
+    /// left in the content of V2 cards edited as V3, where they mean nothing.
+    pub fn v2(&self, decorators: bool) -> Card {
+        let data = self.data.clone();
+        Card {
+            spec: Card::SPEC.to_string(),
+            spec_version: Card::SPEC_VERSION.to_string(),
+            data: CharacterData {
+                name: data.name,
+                description: data.description,
+                personality: data.personality,
+                scenario: data.scenario,
+                first_mes: data.first_mes,
+                mes_example: data.mes_example,
+                creator_notes: data.creator_notes,
+                system_prompt: data.system_prompt,
+                post_history_instructions: data.post_history_instructions,
+                alternate_greetings: data.alternate_greetings,
+                tags: data.tags,
+                creator: data.creator,
+                character_version: data.character_version,
+                extensions: data.extensions,
+                character_book: data.character_book.map(|b| b.into_v2(decorators)),
+                extra: data.extra,
+            },
+            extra: self.extra.clone(),
+        }
+    }
+
+    /// Whether the card uses V3 fields a V2 card would lose.
+    pub fn needs_v3(&self) -> bool {
+        self.data.nickname.is_some() || !self.data.group_only_greetings.is_empty()
+    }
+}
+
+impl From<Card> for CardV3 {
+    fn from(card: Card) -> Self {
+        let data = card.data;
+        CardV3 {
+            spec: Self::SPEC.to_string(),
+            spec_version: Self::SPEC_VERSION.to_string(),
+            data: CharacterDataV3 {
+                name: data.name,
+                description: data.description,
+                personality: data.personality,
+                scenario: data.scenario,
+                first_mes: data.first_mes,
+                mes_example: data.mes_example,
+                creator_notes: data.creator_notes,
+                system_prompt: data.system_prompt,
+                post_history_instructions: data.post_history_instructions,
+                alternate_greetings: data.alternate_greetings,
+                tags: data.tags,
+                creator: data.creator,
+                character_version: data.character_version,
+                extensions: data.extensions,
+                character_book: data.character_book.map(Lorebook::from),
+                nickname: None,
+                creator_notes_multilingual: None,
+                source: None,
+                group_only_greetings: vec![],
+                creation_date: None,
+                modification_date: None,
+                assets: None,
+                extra: data.extra,
+            },
+            extra: card.extra,
+        }
+    }
+}
+
+/// The V2 fields, with V3 additions and a lorebook supporting decorators.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CharacterDataV3 {
+    /// The character's display name.
+    pub name: String,
+
+    /// Detailed description of the character to be included in every prompt.
+    pub description: String,
+
+    /// A summary of the character's personality traits.
+    pub personality: String,
+
+    /// The scenario or current context in which the character exists.
+    pub scenario: String,
+
+    /// The character's first message (greeting) used at the start of a conversation.
+    pub first_mes: String,
+
+    /// Example dialogues demonstrating the character's behavior.
+    pub mes_example: String,
+
+    /// Out-of-character notes for the creator's reference.
+    pub creator_notes: String,
+
+    /// Custom system prompt that overrides the default system prompt.
+    pub system_prompt: String,
+
+    /// Post-history instructions inserted after the conversation history.
+    pub post_history_instructions: String,
+
+    /// Array of alternative greeting messages for additional variety.
+    pub alternate_greetings: Vec<String>,
+
+    /// Array of tags for categorization and filtering purposes.
+    pub tags: Vec<String>,
+
+    /// Identifier for the creator of the character card.
+    pub creator: String,
+
+    /// Version of the character card.
+    pub character_version: String,
+
+    /// Custom extension data for additional metadata at the character level.
+    pub extensions: Extensions,
+
+    /// Optional character-specific lorebook containing background lore and dynamic entries.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub character_book: Option<Lorebook>,
+
+    /// Name used in place of `name` for `{{char}}`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nickname: Option<String>,
+
+    /// Creator notes keyed by ISO 639-1 language code.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub creator_notes_multilingual: Option<HashMap<String, String>>,
+
+    /// Where the card comes from, as ids or URLs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<Vec<String>>,
+
+    /// Greetings only used in group chats.
+    #[serde(default)]
+    pub group_only_greetings: Vec<String>,
+
+    /// Creation date as a unix timestamp in seconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub creation_date: Option<i64>,
+
+    /// Last modification date as a unix timestamp in seconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub modification_date: Option<i64>,
+
+    /// Icons, backgrounds and other files belonging to the character.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub assets: Option<Vec<Asset>>,
+
+    /// Fields unknown to the spec, kept as is.
+    #[serde(flatten)]
+    pub extra: Extensions,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Asset {
+    /// "icon", "background", "user_icon", "emotion" or an application specific type.
+    #[serde(rename = "type")]
+    pub kind: String,
+
+    /// URL, data URL, `embeded://` path inside a CharX, or `ccdefault:`.
+    pub uri: String,
+
+    /// Identifies the asset among those of the same type, "main" for the default one.
+    pub name: String,
+
+    /// File extension without the dot, like "png".
+    pub ext: String,
+}
+
+/// Character lorebook of a V3 card.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Lorebook {
+    /// Optional title of the lorebook.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    /// Optional description summarizing the lorebook's content and purpose.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
+    /// The number of recent chat messages to scan for triggering lore entries.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scan_depth: Option<i32>,
+
+    /// The maximum token budget allocated for lore entries.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_budget: Option<i32>,
+
+    /// Flag indicating whether recursive scanning is enabled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recursive_scanning: Option<bool>,
+
+    /// Custom extension data for the lorebook.
+    #[serde(default)]
+    pub extensions: Extensions,
+
+    /// Array of lore entries that comprise the lorebook.
+    pub entries: Vec<LorebookEntry>,
+
+    /// Fields unknown to the spec, kept as is.
+    #[serde(flatten)]
+    pub extra: Extensions,
+}
+
+impl Lorebook {
+    /// V2 frontends know no decorators, with `decorators` the ones with a V2
+    /// equivalent are applied to the entry and the rest dropped from the content.
+    fn into_v2(self, decorators: bool) -> CharacterBook {
+        CharacterBook {
+            name: self.name,
+            description: self.description,
+            scan_depth: self.scan_depth,
+            token_budget: self.token_budget,
+            recursive_scanning: self.recursive_scanning,
+            extensions: self.extensions,
+            entries: self
+                .entries
+                .into_iter()
+                .map(|e| e.into_v2(decorators))
+                .collect(),
+            extra: self.extra,
+        }
+    }
+}
+
+impl From<CharacterBook> for Lorebook {
+    fn from(book: CharacterBook) -> Self {
+        Lorebook {
+            name: book.name,
+            description: book.description,
+            scan_depth: book.scan_depth,
+            token_budget: book.token_budget,
+            recursive_scanning: book.recursive_scanning,
+            extensions: book.extensions,
+            entries: book
+                .entries
+                .into_iter()
+                .map(|entry| LorebookEntry {
+                    keys: entry.keys,
+                    content: entry.content,
+                    extensions: entry.extensions,
+                    enabled: entry.enabled,
+                    insertion_order: entry.insertion_order,
+                    case_sensitive: entry.case_sensitive,
+                    use_regex: false,
+                    name: entry.name,
+                    priority: entry.priority,
+                    id: entry.id.map(Value::from),
+                    comment: entry.comment,
+                    selective: entry.selective,
+                    secondary_keys: entry.secondary_keys,
+                    constant: entry.constant,
+                    position: entry.position,
+                    extra: entry.extra,
+                })
+                .collect(),
+            extra: book.extra,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LorebookEntry {
+    /// List of primary trigger keywords, or regexes when `use_regex` is set.
+    pub keys: Vec<String>,
+
+    /// The lore text to be injected, optionally starting with `@@` decorator lines.
+    pub content: String,
+
+    /// Custom extension data specific to this lore entry.
+    #[serde(default)]
+    pub extensions: Extensions,
+
+    /// Flag indicating whether this entry is active.
+    pub enabled: bool,
+
+    /// Numeric value controlling the order in which triggered entries are injected into the prompt.
+    pub insertion_order: i32,
+
+    /// Optional flag for enabling case-sensitive matching of keys.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub case_sensitive: Option<bool>,
+
+    /// Keys are regexes instead of plain words.
+    #[serde(default)]
+    pub use_regex: bool,
+
+    /// Optional internal name for the lore entry.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    /// Optional priority value used for dropping the entry if the combined lore exceeds the token budget.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub priority: Option<i32>,
+
+    /// Optional internal identifier for this entry, a number or a string.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<Value>,
+
+    /// Optional comment or note about the entry for human reference.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+
+    /// If true, this entry will trigger only if both a primary keyword and a secondary keyword are present.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub selective: Option<bool>,
+
+    /// Optional secondary trigger keywords used in conjunction with keys when selective is true.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secondary_keys: Option<Vec<String>>,
+
+    /// If true, this entry is always injected into the prompt regardless of trigger keywords.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub constant: Option<bool>,
+
+    /// Specifies the insertion position of this entry's content relative to the character's main definition.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub position: Option<String>,
+
+    /// Fields unknown to the spec, kept as is.
+    #[serde(flatten)]
+    pub extra: Extensions,
+}
+
+impl LorebookEntry {
+    /// An enabled entry without keys or content.
+    pub fn new(id: Option<Value>) -> Self {
+        LorebookEntry {
+            keys: vec![],
+            content: String::new(),
+            extensions: Extensions::new(),
+            enabled: true,
+            insertion_order: 100,
+            case_sensitive: None,
+            use_regex: false,
+            name: None,
+            priority: None,
+            id,
+            comment: None,
+            selective: None,
+            secondary_keys: None,
+            constant: None,
+            position: None,
+            extra: Extensions::new(),
+        }
+    }
+
+    /// Splits the content into its leading decorators and the text to inject.
+    pub fn decorators(&self) -> (Vec<Decorator>, String) {
+        Decorator::parse(&self.content)
+    }
+
+    fn into_v2(self, decorators: bool) -> Entry {
+        let (decorators, content) = match decorators {
+            true => self.decorators(),
+            false => (vec![], self.content.clone()),
+        };
+        let mut entry = Entry {
+            id: self.id.as_ref().and_then(Value::as_i64).map(|id| id as i32),
+            keys: self.keys,
+            content,
+            extensions: self.extensions,
+            enabled: self.enabled,
+            insertion_order: self.insertion_order,
+            case_sensitive: self.case_sensitive,
+            name: self.name,
+            priority: self.priority,
+            comment: self.comment,
+            selective: self.selective,
+            secondary_keys: self.secondary_keys,
+            constant: self.constant,
+            position: self.position,
+            extra: self.extra,
+        };
+        for decorator in decorators {
+            match decorator {
+                Decorator::Activate => entry.constant = Some(true),
+                Decorator::DontActivate => entry.enabled = false,
+                Decorator::AdditionalKeys(keys) => entry.keys.extend(keys),
+                Decorator::Depth(depth) => {
+                    entry.extensions.insert("depth".to_string(), depth.into());
+                }
+                Decorator::ScanDepth(depth) => {
+                    entry
+                        .extensions
+                        .insert("scan_depth".to_string(), depth.into());
+                }
+                _ => (),
+            }
+        }
+        entry
+    }
+}
+
+/// Per-entry activation and insertion settings, written as `@@name value`
+/// lines at the start of the content.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Decorator {
+    /// Always activates the entry.
+    Activate,
+    /// Never activates the entry.
+    DontActivate,
+    /// Inserts the entry this many messages from the end of the chat.
+    Depth(u32),
+    /// Inserts the entry this many messages from the start of the chat.
+    ReverseDepth(u32),
+    /// Inserts the entry at a named position, like "after_desc".
+    Position(String),
+    /// Only activates after this many user messages.
+    ActivateOnlyAfter(u32),
+    /// Only activates every this many user messages.
+    ActivateOnlyEvery(u32),
+    /// Stays active once matched.
+    KeepActivateAfterMatch,
+    /// Deactivates once matched.
+    DontActivateAfterMatch,
+    /// Scans this many recent messages instead of the book's scan depth.
+    ScanDepth(u32),
+    /// Keys that also trigger the entry.
+    AdditionalKeys(Vec<String>),
+    /// Keys that prevent the entry from triggering.
+    ExcludeKeys(Vec<String>),
+    /// Only activates when the chat started with this greeting index.
+    IsGreeting(u32),
+    /// The entry describes the user rather than the character.
+    IsUserPersona,
+    /// Dropped first when the context is full.
+    IgnoreOnMaxContext,
+    /// Role of the inserted message: "system", "user" or "assistant".
+    Role(String),
+    /// A decorator this application does not know.
+    Unknown(String, Option<String>),
+}
+
+impl Decorator {
+    /// Parses the leading `@@` lines of `content`. `@@@` lines are fallbacks,
+    /// used in place of the previous decorator when it is unknown.
+    pub fn parse(content: &str) -> (Vec<Decorator>, String) {
+        let mut decorators: Vec<Decorator> = vec![];
+        let mut lines = content.lines().peekable();
+        while let Some(line) = lines.next_if(|l| l.starts_with("@@")) {
+            match line.strip_prefix("@@@") {
+                Some(fallback) => {
+                    if let Some(last) = decorators.last_mut()
+                        && matches!(last, Decorator::Unknown(..))
+                    {
+                        *last = Self::parse_line(fallback);
+                    }
+                }
+                None => decorators.push(Self::parse_line(&line[2..])),
+            }
+        }
+        (decorators, lines.collect::<Vec<&str>>().join("\n"))
+    }
+
+    fn parse_line(line: &str) -> Decorator {
+        let (name, value) = match line.trim().split_once(char::is_whitespace) {
+            Some((name, value)) => (name, Some(value.trim().to_string())),
+            None => (line.trim(), None),
+        };
+        let number = || value.as_deref().and_then(|v| v.parse().ok());
+        let list = || {
+            value
+                .as_deref()
+                .map(|v| v.split(',').map(|k| k.trim().to_string()).collect())
+        };
+        let decorator = match name {
+            "activate" => Some(Decorator::Activate),
+            "dont_activate" => Some(Decorator::DontActivate),
+            "depth" => number().map(Decorator::Depth),
+            "reverse_depth" => number().map(Decorator::ReverseDepth),
+            "position" => value.clone().map(Decorator::Position),
+            "activate_only_after" => number().map(Decorator::ActivateOnlyAfter),
+            "activate_only_every" => number().map(Decorator::ActivateOnlyEvery),
+            "keep_activate_after_match" => Some(Decorator::KeepActivateAfterMatch),
+            "dont_activate_after_match" => Some(Decorator::DontActivateAfterMatch),
+            "scan_depth" => number().map(Decorator::ScanDepth),
+            "additional_keys" => list().map(Decorator::AdditionalKeys),
+            "exclude_keys" => list().map(Decorator::ExcludeKeys),
+            "is_greeting" => number().map(Decorator::IsGreeting),
+            "is_user_persona" => Some(Decorator::IsUserPersona),
+            "ignore_on_max_context" => Some(Decorator::IgnoreOnMaxContext),
+            "role" => value.clone().map(Decorator::Role),
+            _ => None,
+        };
+        decorator.unwrap_or(Decorator::Unknown(name.to_string(), value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{CardV3, Decorator};
+    use crate::persona::CharData;
+
+    fn sample_json() -> String {
+        json!({
+            "spec": "chara_card_v3",
+            "spec_version": "3.0",
+            "data": {
+                "name": "Aria Windsong",
+                "nickname": "Aria",
+                "description": "{{char}} sings for {{user}}.",
+                "personality": "", "scenario": "", "mes_example": "",
+                "first_mes": "Hi {{user}}, I am {{char}}.",
+                "alternate_greetings": [],
+                "group_only_greetings": ["Hello everyone!"],
+                "creator_notes": "", "system_prompt": "", "post_history_instructions": "",
+                "tags": [], "creator": "", "character_version": "", "extensions": {},
+                "creation_date": 1700000000,
+                "assets": [{"type": "icon", "uri": "ccdefault:", "name": "main", "ext": "png"}],
+                "character_book": {
+                    "extensions": {},
+                    "entries": [{
+                        "keys": ["lute"],
+                        "content": "@@depth 4\n@@additional_keys song, ballad\nHer lute was a gift.",
+                        "extensions": {}, "enabled": true, "insertion_order": 0,
+                        "use_regex": false, "id": "lute-entry"
+                    }]
+                }
+            }
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn v3_card_uses_nickname_and_skips_group_greetings() {
+        let card = CardV3::load_from_json(&sample_json()).unwrap();
+        assert_eq!(card.name(), "Aria Windsong");
+        assert_eq!(card.system_prompt(Some("Bob")), "Aria sings for Bob.");
+        assert_eq!(
+            card.greetings(Some("Bob")),
+            Some(vec!["Hi Bob, I am Aria.".to_string()])
+        );
+    }
+
+    #[test]
+    fn v2_downgrade_applies_decorators() {
+        let card = CardV3::load_from_json(&sample_json()).unwrap();
+        let book = card.card().data.character_book.unwrap();
+        let entry = &book.entries[0];
+        assert_eq!(entry.content, "Her lute was a gift.");
+        assert_eq!(entry.keys, vec!["lute", "song", "ballad"]);
+        assert_eq!(entry.extensions["depth"], 4);
+        assert_eq!(entry.id, None);
+    }
+
+    #[test]
+    fn fallback_replaces_unknown_decorator() {
+        let (decorators, content) =
+            Decorator::parse("@@instruct_depth 2\n@@@depth 3\n@@activate\n@@@depth 9\nText");
+        assert_eq!(decorators, vec![Decorator::Depth(3), Decorator::Activate]);
+        assert_eq!(content, "Text");
+    }
+
+    #[test]
+    fn v2_card_is_not_a_v3_card() {
+        let v2 = sample_json().replace("chara_card_v3", "chara_card_v2");
+        assert!(CardV3::load_from_json(&v2).is_err());
+    }
+}
@@ -0,0 +1,224 @@
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{Result, anyhow};
+use log::trace;
+use serde_json::Value;
+
+use crate::{
+    persona::{
+        Persona,
+        card_v3::Asset,
+        loader::{PersonaLoader, Subdir},
+    },
+    utils::zip,
+};
+
+const CARD: &str = "card.json";
+const EMBEDDED: &str = "embeded://";
+/// Where the avatar goes when the persona has no icon asset of its own.
+const ICON: &str = "assets/icon/images/main.png";
+
+/// `.charx` packages: a zip with the V3 `card.json` and the files its assets point to.
+pub struct CharX {}
+
+impl CharX {
+    pub fn is_charx(path: &Path) -> bool {
+        path.extension().is_some_and(|e| e == "charx")
+    }
+
+    /// Unpacks a package into a new character directory, the main icon
+    /// becoming the avatar, and loads it.
+    pub fn import(path: &Path) -> Result<Persona> {
+        Self::import_into(path, &PersonaLoader::cache_path(&Subdir::Chars))
+    }
+
+    /// [`Self::import`] into a directory of `root`.
+    fn import_into(path: &Path, root: &Path) -> Result<Persona> {
+        let files = zip::read(&fs::read(path)?)?;
+        let (_, card) = files
+            .iter()
+            .find(|(name, _)| name == CARD)
+            .ok_or(anyhow!("No {CARD} in {}", path.display()))?;
+        let card: Value = serde_json::from_slice(card)?;
+        let name = card
+            .get("data")
+            .and_then(|d| d.get("name"))
+            .and_then(Value::as_str)
+            .ok_or(anyhow!("Card has no name"))?;
+
+        let mut targets = vec![];
+        for (file, data) in &files {
+            match Self::safe_path(file) {
+                Some(relative) => targets.push((relative, data)),
+                None => return Err(anyhow!("Unsafe path {file} in {}", path.display())),
+            }
+        }
+
+        let dir = PersonaLoader::unique_dir(root, name);
+        for (relative, data) in targets {
+            let target = dir.join(relative);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(target, data)?;
+        }
+        if let Some(icon) = Self::icon(&card, &files) {
+            fs::write(dir.join("avatar.png"), PersonaLoader::to_png(icon)?)?;
+        }
+        trace!("Unpacked {} into {}", path.display(), dir.display());
+        PersonaLoader::load_persona_dir(dir, Subdir::Chars)
+    }
+
+    /// Bundles the card, the avatar and the character's `assets` directory.
+    pub fn export(persona: &Persona) -> Result<Vec<u8>> {
+        let mut files = vec![];
+        let dir = persona.path();
+        if dir.is_dir() {
+            Self::collect(dir, &dir.join("assets"), &mut files)?;
+        }
+
+        let mut card = persona.card_v3();
+        let assets = card.data.assets.get_or_insert_with(Vec::new);
+        let has_icon = assets.iter().any(|asset| {
+            asset.kind == "icon"
+                && asset.name == "main"
+                && asset
+                    .uri
+                    .strip_prefix(EMBEDDED)
+                    .is_some_and(|path| files.iter().any(|(name, _)| name == path))
+        });
+        if !has_icon {
+            assets.retain(|asset| !(asset.kind == "icon" && asset.name == "main"));
+            assets.push(Asset {
+                kind: "icon".to_string(),
+                uri: format!("{EMBEDDED}{ICON}"),
+                name: "main".to_string(),
+                ext: "png".to_string(),
+            });
+            files.retain(|(name, _)| name != ICON);
+            files.push((ICON.to_string(), persona.original_avatar_png()?));
+        }
+        files.insert(0, (CARD.to_string(), serde_json::to_vec_pretty(&card)?));
+        zip::write(&files)
+    }
+
+    /// The data of the main icon, or of the first icon.
+    fn icon<'a>(card: &Value, files: &'a [(String, Vec<u8>)]) -> Option<&'a [u8]> {
+        let assets = card.get("data")?.get("assets")?.as_array()?;
+        let icons: Vec<&Value> = assets
+            .iter()
+            .filter(|a| a.get("type").is_some_and(|t| t == "icon"))
+            .collect();
+        let icon = icons
+            .iter()
+            .find(|a| a.get("name").is_some_and(|n| n == "main"))
+            .or(icons.first())?;
+        let path = icon.get("uri")?.as_str()?.strip_prefix(EMBEDDED)?;
+        files
+            .iter()
+            .find(|(name, _)| name == path)
+            .map(|(_, data)| data.as_slice())
+    }
+
+    /// Zip entry names are relative, anything escaping the directory is refused.
+    fn safe_path(name: &str) -> Option<PathBuf> {
+        let path = PathBuf::from(name);
+        path.components()
+            .all(|c| matches!(c, Component::Normal(_)))
+            .then_some(path)
+    }
+
+    fn collect(root: &Path, dir: &Path, files: &mut Vec<(String, Vec<u8>)>) -> Result<()> {
+        if !dir.is_dir() {
+            return Ok(());
+        }
+        for entry in fs::read_dir(dir)?.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                Self::collect(root, &path, files)?;
+            } else if let Ok(relative) = path.strip_prefix(root) {
+                let name = relative
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/");
+                files.push((name, fs::read(&path)?));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{CARD, CharX, ICON};
+    use crate::{
+        persona::{
+            card::Card,
+            card_v3::CardV3,
+            loader::{PersonaLoader, Subdir},
+        },
+        utils::{files::test_dir, zip},
+    };
+
+    #[test]
+    fn packages_round_trip() {
+        let mut card = CardV3::from(Card::new("Aria", "A wandering bard."));
+        card.data.first_mes = "Hello {{user}}.".to_string();
+        let avatar = fs::read("assets/char.png").unwrap();
+        let dir = test_dir("Aria");
+        let char =
+            PersonaLoader::save_card(&dir, &card, true, Some(&avatar), Subdir::Chars).unwrap();
+        fs::create_dir_all(dir.join("assets/other")).unwrap();
+        fs::write(dir.join("assets/other/song.txt"), "La la la").unwrap();
+
+        let package = CharX::export(&char).unwrap();
+        let files = zip::read(&package).unwrap();
+        let path = test_dir("packages").join("aria.charx");
+        fs::write(&path, &package).unwrap();
+        let root = test_dir("chars");
+        let imported = CharX::import_into(&path, &root).unwrap();
+
+        assert_eq!(imported.path(), root.join("Aria"));
+        let (_, exported) = files.iter().find(|(name, _)| name == CARD).unwrap();
+        assert_eq!(
+            serde_json::to_value(imported.card_v3()).unwrap(),
+            serde_json::from_slice::<serde_json::Value>(exported).unwrap()
+        );
+        assert_eq!(imported.card_v3().data.first_mes, "Hello {{user}}.");
+        assert_eq!(
+            fs::read(root.join("Aria/assets/other/song.txt")).unwrap(),
+            b"La la la"
+        );
+        assert_eq!(
+            fs::read(root.join("Aria").join(ICON)).unwrap(),
+            char.original_avatar_png().unwrap()
+        );
+        assert!(root.join("Aria/avatar.png").exists());
+    }
+
+    #[test]
+    fn unsafe_entries_are_refused() {
+        let card = br#"{"spec": "chara_card_v3", "data": {"name": "Aria"}}"#.to_vec();
+        for name in ["../x", "/abs", "a/../../b"] {
+            let files = vec![
+                (CARD.to_string(), card.clone()),
+                (name.to_string(), b"escaped".to_vec()),
+            ];
+            let path = test_dir("packages").join("aria.charx");
+            fs::write(&path, zip::write(&files).unwrap()).unwrap();
+            let root = test_dir("chars");
+
+            let Err(e) = CharX::import_into(&path, &root) else {
+                panic!("{name} was unpacked");
+            };
+            assert!(e.to_string().starts_with(&format!("Unsafe path {name}")));
+            assert_eq!(fs::read_dir(&root).unwrap().count(), 0);
+        }
+    }
+}
@@ -0,0 +1,497 @@
+use std::fmt::Display;
+
+use anyhow::{Result, anyhow};
+use log::warn;
+use serde_json::{Map, Value, json};
+
+use crate::persona::{card::Card, card_v3::CardV3};
+
+/// Fields of a flat V1 card, moved under `data` when upgrading to V2.
+const V1_FIELDS: [&str; 6] = [
+    "name",
+    "description",
+    "personality",
+    "scenario",
+    "first_mes",
+    "mes_example",
+];
+
+/// Expected shape of a field, with the default used when it is missing.
+#[derive(Clone, Copy)]
+enum Kind {
+    Str,
+    StrArray,
+    Object,
+    Bool(bool),
+    Int(i64),
+    OptStr,
+    OptStrArray,
+    OptBool,
+    OptInt,
+}
+
+const DATA_V2: [(&str, Kind); 14] = [
+    ("name", Kind::Str),
+    ("description", Kind::Str),
+    ("personality", Kind::Str),
+    ("scenario", Kind::Str),
+    ("first_mes", Kind::Str),
+    ("mes_example", Kind::Str),
+    ("creator_notes", Kind::Str),
+    ("system_prompt", Kind::Str),
+    ("post_history_instructions", Kind::Str),
+    ("alternate_greetings", Kind::StrArray),
+    ("tags", Kind::StrArray),
+    ("creator", Kind::Str),
+    ("character_version", Kind::Str),
+    ("extensions", Kind::Object),
+];
+
+const DATA_V3: [(&str, Kind); 5] = [
+    ("nickname", Kind::OptStr),
+    ("source", Kind::OptStrArray),
+    ("group_only_greetings", Kind::StrArray),
+    ("creation_date", Kind::OptInt),
+    ("modification_date", Kind::OptInt),
+];
+
+const BOOK: [(&str, Kind); 6] = [
+    ("name", Kind::OptStr),
+    ("description", Kind::OptStr),
+    ("scan_depth", Kind::OptInt),
+    ("token_budget", Kind::OptInt),
+    ("recursive_scanning", Kind::OptBool),
+    ("extensions", Kind::Object),
+];
+
+const ENTRY: [(&str, Kind); 13] = [
+    ("keys", Kind::StrArray),
+    ("content", Kind::Str),
+    ("extensions", Kind::Object),
+    ("enabled", Kind::Bool(true)),
+    ("insertion_order", Kind::Int(0)),
+    ("case_sensitive", Kind::OptBool),
+    ("name", Kind::OptStr),
+    ("priority", Kind::OptInt),
+    ("comment", Kind::OptStr),
+    ("selective", Kind::OptBool),
+    ("secondary_keys", Kind::OptStrArray),
+    ("constant", Kind::OptBool),
+    ("position", Kind::OptStr),
+];
+
+/// Something that had to be changed for a card to load.
+pub struct Coercion {
+    /// Where in the card, like `data.character_book.entries[2].keys`.
+    pub path: String,
+    pub kind: CoercionKind,
+}
+
+pub enum CoercionKind {
+    /// Absent, set to its default.
+    Missing,
+    /// `null`, set to its default.
+    Null,
+    /// Converted from another JSON type.
+    Converted(&'static str),
+    /// Of a type that could not be converted, replaced by its default.
+    Replaced(&'static str),
+    /// A flat V1 card moved under `data` as a V2 card.
+    UpgradedFromV1,
+}
+
+impl Display for Coercion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.kind {
+            CoercionKind::Missing => write!(f, "{}: missing", self.path),
+            CoercionKind::Null => write!(f, "{}: null", self.path),
+            CoercionKind::Converted(from) => write!(f, "{}: converted from {from}", self.path),
+            CoercionKind::Replaced(from) => write!(f, "{}: dropped {from}", self.path),
+            CoercionKind::UpgradedFromV1 => write!(f, "{}: upgraded from V1", self.path),
+        }
+    }
+}
+
+/// Normalizes a V1 or V2 card so it deserializes as a `Card`.
+pub fn v2(value: Value) -> Result<(Value, Vec<Coercion>)> {
+    let mut coercions = vec![];
+    let mut card = match value {
+        Value::Object(card) => card,
+        _ => return Err(anyhow!("Not a card")),
+    };
+    match card.get("spec").and_then(Value::as_str) {
+        Some(spec) if spec == Card::SPEC => (),
+        Some(spec) => return Err(anyhow!("Not a V2 card: {spec}")),
+        None if card.get("data").is_some_and(Value::is_object) => (),
+        None if is_v1(&card) => {
+            let data: Map<String, Value> = V1_FIELDS
+                .iter()
+                .filter_map(|field| card.get(*field).map(|v| (field.to_string(), v.clone())))
+                .collect();
+            card.insert("data".to_string(), Value::Object(data));
+            coercions.push(Coercion {
+                path: String::new(),
+                kind: CoercionKind::UpgradedFromV1,
+            });
+        }
+        None => return Err(anyhow!("Not a card")),
+    }
+    fix(&mut card, "spec", json!(Card::SPEC), &mut coercions);
+    fix(
+        &mut card,
+        "spec_version",
+        json!(Card::SPEC_VERSION),
+        &mut coercions,
+    );
+    data(&mut card, &DATA_V2, false, &mut coercions)?;
+    Ok((Value::Object(card), coercions))
+}
+
+/// Normalizes a V3 card so it deserializes as a `CardV3`.
+pub fn v3(value: Value) -> Result<(Value, Vec<Coercion>)> {
+    let mut coercions = vec![];
+    let mut card = match value {
+        Value::Object(card) => card,
+        _ => return Err(anyhow!("Not a card")),
+    };
+    match card.get("spec").and_then(Value::as_str) {
+        Some(spec) if spec == CardV3::SPEC => (),
+        _ => return Err(anyhow!("Not a V3 card")),
+    }
+    fix(
+        &mut card,
+        "spec_version",
+        json!(CardV3::SPEC_VERSION),
+        &mut coercions,
+    );
+    let schema: Vec<(&str, Kind)> = DATA_V2.iter().chain(DATA_V3.iter()).copied().collect();
+    data(&mut card, &schema, true, &mut coercions)?;
+    Ok((Value::Object(card), coercions))
+}
+
+/// Normalizes a standalone lorebook so it deserializes as a `CharacterBook`.
+pub fn book(value: Value) -> Result<(Value, Vec<Coercion>)> {
+    let mut coercions = vec![];
+    let mut book = match value {
+        Value::Object(book) if book.contains_key("entries") => book,
+        _ => return Err(anyhow!("Not a lorebook")),
+    };
+    lorebook(&mut book, "book", false, &mut coercions);
+    Ok((Value::Object(book), coercions))
+}
+
+/// Logs what was coerced to load `name`, like "Card Aria", in one line.
+pub fn log(name: &str, coercions: &[Coercion]) {
+    if !coercions.is_empty() {
+        warn!(
+            "{name} loaded with {} coercions: {}",
+            coercions.len(),
+            coercions
+                .iter()
+                .map(|c| c.to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        );
+    }
+}
+
+fn is_v1(card: &Map<String, Value>) -> bool {
+    card.get("name").is_some_and(Value::is_string)
+        && V1_FIELDS[2..].iter().any(|field| card.contains_key(*field))
+}
+
+fn data(
+    card: &mut Map<String, Value>,
+    schema: &[(&str, Kind)],
+    v3: bool,
+    coercions: &mut Vec<Coercion>,
+) -> Result<()> {
+    let data = card
+        .get_mut("data")
+        .and_then(Value::as_object_mut)
+        .ok_or(anyhow!("Card has no data"))?;
+    if !data.get("name").is_some_and(Value::is_string) {
+        return Err(anyhow!("Card has no name"));
+    }
+    fields(data, schema, "data", coercions);
+    match data.get_mut("character_book") {
+        Some(Value::Object(book)) => lorebook(book, "data.character_book", v3, coercions),
+        Some(Value::Null) | None => {
+            data.remove("character_book");
+        }
+        Some(other) => {
+            coerced("data.character_book", Some(&other.clone()), coercions);
+            data.remove("character_book");
+        }
+    }
+    Ok(())
+}
+
+fn lorebook(book: &mut Map<String, Value>, path: &str, v3: bool, coercions: &mut Vec<Coercion>) {
+    fields(book, &BOOK, path, coercions);
+    let mut entries = match book.remove("entries") {
+        Some(Value::Array(entries)) => entries,
+        other => {
+            coerced(&format!("{path}.entries"), other.as_ref(), coercions);
+            vec![]
+        }
+    };
+    entries = entries
+        .into_iter()
+        .enumerate()
+        .filter_map(|(idx, mut entry)| {
+            let path = format!("{path}.entries[{idx}]");
+            let Some(object) = entry.as_object_mut() else {
+                coerced(&path, Some(&entry), coercions);
+                return None;
+            };
+            fields(object, &ENTRY, &path, coercions);
+            match v3 {
+                true => fields(
+                    object,
+                    &[("use_regex", Kind::Bool(false))],
+                    &path,
+                    coercions,
+                ),
+                false => fields(object, &[("id", Kind::OptInt)], &path, coercions),
+            }
+            Some(entry)
+        })
+        .collect();
+    book.insert("entries".to_string(), Value::Array(entries));
+}
+
+fn fields(
+    object: &mut Map<String, Value>,
+    schema: &[(&str, Kind)],
+    path: &str,
+    coercions: &mut Vec<Coercion>,
+) {
+    for (field, kind) in schema {
+        let path = format!("{path}.{field}");
+        let value = object.remove(*field);
+        let kind_of = |v: &Value| -> CoercionKind {
+            match v {
+                Value::Null => CoercionKind::Null,
+                _ => CoercionKind::Replaced(type_name(v)),
+            }
+        };
+        let (fixed, coercion) = match (kind, value) {
+            (Kind::OptStr | Kind::OptStrArray | Kind::OptBool | Kind::OptInt, None) => continue,
+            (
+                Kind::OptStr | Kind::OptStrArray | Kind::OptBool | Kind::OptInt,
+                Some(Value::Null),
+            ) => {
+                continue;
+            }
+            (_, None) => (default(*kind), Some(CoercionKind::Missing)),
+            (Kind::Str | Kind::OptStr, Some(Value::String(s))) => (json!(s), None),
+            (Kind::Str | Kind::OptStr, Some(v @ (Value::Number(_) | Value::Bool(_)))) => (
+                json!(v.to_string()),
+                Some(CoercionKind::Converted(type_name(&v))),
+            ),
+            (Kind::Str | Kind::OptStr, Some(Value::Array(items))) => (
+                json!(strings(&items).join("\n")),
+                Some(CoercionKind::Converted("array")),
+            ),
+            (Kind::StrArray | Kind::OptStrArray, Some(Value::Array(items))) => {
+                let fixed = strings(&items);
+                let converted = (fixed.len() != items.len()
+                    || items.iter().any(|i| !i.is_string()))
+                .then_some(CoercionKind::Converted("mixed array"));
+                (json!(fixed), converted)
+            }
+            (Kind::StrArray | Kind::OptStrArray, Some(Value::String(s))) => (
+                json!(
+                    s.split(',')
+                        .map(|k| k.trim())
+                        .filter(|k| !k.is_empty())
+                        .collect::<Vec<&str>>()
+                ),
+                Some(CoercionKind::Converted("string")),
+            ),
+            (Kind::Object, Some(v @ Value::Object(_))) => (v, None),
+            (Kind::Bool(_) | Kind::OptBool, Some(Value::Bool(b))) => (json!(b), None),
+            (Kind::Bool(_) | Kind::OptBool, Some(v @ Value::Number(_))) => (
+                json!(v.as_f64() != Some(0.0)),
+                Some(CoercionKind::Converted("number")),
+            ),
+            (Kind::Bool(_) | Kind::OptBool, Some(Value::String(s)))
+                if s.parse::<bool>().is_ok() =>
+            {
+                (json!(s == "true"), Some(CoercionKind::Converted("string")))
+            }
+            (Kind::Int(_) | Kind::OptInt, Some(v @ Value::Number(_))) => match v.as_i64() {
+                Some(_) => (v, None),
+                None => (
+                    json!(v.as_f64().unwrap_or_default() as i64),
+                    Some(CoercionKind::Converted("float")),
+                ),
+            },
+            (Kind::Int(_) | Kind::OptInt, Some(Value::String(s)))
+                if s.trim().parse::<i64>().is_ok() =>
+            {
+                (
+                    json!(s.trim().parse::<i64>().unwrap_or_default()),
+                    Some(CoercionKind::Converted("string")),
+                )
+            }
+            (Kind::OptStr | Kind::OptStrArray | Kind::OptBool | Kind::OptInt, Some(v)) => {
+                coercions.push(Coercion {
+                    path,
+                    kind: kind_of(&v),
+                });
+                continue;
+            }
+            (_, Some(v)) => (default(*kind), Some(kind_of(&v))),
+        };
+        object.insert(field.to_string(), fixed);
+        if let Some(kind) = coercion {
+            coercions.push(Coercion { path, kind });
+        }
+    }
+}
+
+/// Records a value that was dropped, unless it was just absent.
+fn coerced(path: &str, value: Option<&Value>, coercions: &mut Vec<Coercion>) {
+    let kind = match value {
+        None => CoercionKind::Missing,
+        Some(Value::Null) => CoercionKind::Null,
+        Some(v) => CoercionKind::Replaced(type_name(v)),
+    };
+    coercions.push(Coercion {
+        path: path.to_string(),
+        kind,
+    });
+}
+
+/// Sets a top level string that must have a fixed value.
+fn fix(card: &mut Map<String, Value>, field: &str, value: Value, coercions: &mut Vec<Coercion>) {
+    if !card.get(field).is_some_and(Value::is_string) {
+        coerced(field, card.get(field), coercions);
+        card.insert(field.to_string(), value);
+    }
+}
+
+fn default(kind: Kind) -> Value {
+    match kind {
+        Kind::Str => json!(""),
+        Kind::StrArray => json!([]),
+        Kind::Object => json!({}),
+        Kind::Bool(b) => json!(b),
+        Kind::Int(i) => json!(i),
+        Kind::OptStr | Kind::OptStrArray | Kind::OptBool | Kind::OptInt => Value::Null,
+    }
+}
+
+/// Keeps strings, numbers and booleans as strings, drops the rest.
+fn strings(items: &[Value]) -> Vec<String> {
+    items
+        .iter()
+        .filter_map(|item| match item {
+            Value::String(s) => Some(s.clone()),
+            Value::Number(_) | Value::Bool(_) => Some(item.to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "bool",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::CoercionKind;
+    use crate::persona::{CharData, card::Card};
+
+    #[test]
+    fn v1_card_is_upgraded() {
+        let v1 = json!({
+            "name": "Aria",
+            "description": "{{char}} is a bard.",
+            "personality": "cheerful",
+            "scenario": "A tavern.",
+            "first_mes": "Hello {{user}}!",
+            "mes_example": "",
+        });
+        let (value, coercions) = super::v2(v1.clone()).unwrap();
+        assert!(matches!(coercions[0].kind, CoercionKind::UpgradedFromV1));
+        let card: Card = serde_json::from_value(value).unwrap();
+        assert_eq!(card.data.personality, "cheerful");
+        assert_eq!(
+            card.greetings(Some("Bob")),
+            Some(vec!["Hello Bob!".to_string()])
+        );
+        // The flat fields stay at the top level for V1 readers.
+        assert_eq!(
+            serde_json::to_value(&card).unwrap()["first_mes"],
+            v1["first_mes"]
+        );
+    }
+
+    #[test]
+    fn missing_and_mistyped_fields_are_coerced() {
+        let card = json!({
+            "spec": "chara_card_v2",
+            "data": {
+                "name": "Aria",
+                "description": null,
+                "tags": "bard, music",
+                "character_version": 2,
+                "character_book": {
+                    "entries": [
+                        {"keys": ["lute", 3], "content": "A lute.", "insertion_order": "5", "id": "7"},
+                        "not an entry"
+                    ]
+                }
+            }
+        });
+        let (value, coercions) = super::v2(card).unwrap();
+        let card: Card = serde_json::from_value(value).unwrap();
+        assert_eq!(card.data.description, "");
+        assert_eq!(card.data.tags, vec!["bard", "music"]);
+        assert_eq!(card.data.character_version, "2");
+        let book = card.data.character_book.unwrap();
+        assert_eq!(book.entries.len(), 1);
+        assert_eq!(book.entries[0].keys, vec!["lute", "3"]);
+        assert_eq!(book.entries[0].insertion_order, 5);
+        assert_eq!(book.entries[0].id, Some(7));
+        assert!(book.entries[0].enabled);
+
+        let paths: Vec<String> = coercions.iter().map(|c| c.to_string()).collect();
+        assert!(paths.contains(&"spec_version: missing".to_string()));
+        assert!(paths.contains(&"data.description: null".to_string()));
+        assert!(paths.contains(&"data.tags: converted from string".to_string()));
+        assert!(paths.contains(&"data.character_book.entries[1]: dropped string".to_string()));
+    }
+
+    #[test]
+    fn unknown_fields_are_kept() {
+        let card = json!({
+            "spec": "chara_card_v2",
+            "spec_version": "2.0",
+            "create_date": "2024-01-01",
+            "data": {"name": "Aria", "fav": true},
+        });
+        let loaded = Card::load_from_json(&card.to_string()).unwrap();
+        let saved = serde_json::to_value(&*loaded).unwrap();
+        assert_eq!(saved["create_date"], "2024-01-01");
+        assert_eq!(saved["data"]["fav"], true);
+    }
+
+    #[test]
+    fn basic_persona_is_not_a_card() {
+        let basic = json!({"name": "Bob", "description": "A user."});
+        assert!(super::v2(basic).is_err());
+    }
+}
@@ -1,20 +1,27 @@
 use anyhow::{Result, anyhow};
 use iced::{
     advanced::{
-        graphics::image::image_rs::{ImageBuffer, Rgba, imageops::crop_imm, open},
+        graphics::image::image_rs::{
+            DynamicImage, ImageBuffer, ImageOutputFormat, Rgba, imageops::crop_imm,
+            load_from_memory, open,
+        },
         image::Bytes,
     },
     widget::image::Handle,
 };
-use log::{error, trace};
+use log::{error, trace, warn};
 use std::{
     fs::{self, File},
-    path::PathBuf,
+    io::Cursor,
+    path::{Path, PathBuf},
     rc::Rc,
     time::SystemTime,
 };
 
-use crate::persona::{CharData, Persona, basic::Basic, card::Card};
+use crate::{
+    persona::{CharData, Persona, basic::Basic, card::Card, card_v3::CardV3, png},
+    utils::files::{self, write_atomic},
+};
 
 pub enum Subdir {
     Chars,
@@ -85,20 +92,79 @@ impl PersonaLoader {
         let mut personas = vec![];
         for entry in (fs::read_dir(dir)?).flatten() {
             let path = entry.path();
-            if path.is_dir()
-                && let Ok(persona) = Self::try_load_subdir(path, default_handle)
-            {
+            let persona = match path.extension().and_then(|e| e.to_str()) {
+                _ if path.is_dir() => Self::try_load_subdir(path, default_handle),
+                Some("png") => Self::try_load_card_png(path),
+                _ => continue,
+            };
+            if let Ok(persona) = persona {
                 personas.push(persona);
             }
         }
         Ok(personas)
     }
 
+    /// Loads the user persona in `folder` of the users directory.
+    pub fn load_user(folder: &str) -> Result<Persona> {
+        let path = Self::cache_path(&Subdir::Users).join(folder);
+        match path.is_file() {
+            true => Self::try_load_card_png(path),
+            false => Self::try_load_subdir(path, &Subdir::Users.default_handle()),
+        }
+    }
+
+    /// Loads the selected user persona in `folder`, the most recent one when
+    /// none was selected or it is gone.
+    pub fn load_selected_user(folder: Option<&str>) -> Persona {
+        if let Some(folder) = folder {
+            match Self::load_user(folder) {
+                Ok(user) => return user,
+                Err(e) => warn!("Selected user persona {folder}: {e}"),
+            }
+        }
+        Self::load_most_recent_from_cache(Subdir::Users)
+    }
+
+    /// Loads the user persona locked in `folder`, `selected` when there is no
+    /// lock or the locked persona is gone.
+    pub fn load_locked_user(folder: Option<&str>, selected: &Persona) -> Persona {
+        if let Some(folder) = folder {
+            match Self::load_user(folder) {
+                Ok(user) => return user,
+                Err(e) => warn!("Locked user persona {folder}: {e}"),
+            }
+        }
+        selected.clone()
+    }
+
+    /// Loads a single persona directory.
+    pub fn load_persona_dir(dir: PathBuf, subdir: Subdir) -> Result<Persona> {
+        Self::try_load_subdir(dir, &subdir.default_handle())
+    }
+
+    /// A directory for a new persona named `name`, not used by any other one.
+    pub fn new_persona_dir(subdir: Subdir, name: &str) -> PathBuf {
+        Self::unique_dir(&Self::cache_path(&subdir), name)
+    }
+
+    /// A directory of `root` named after `name`, numbered when already taken.
+    pub fn unique_dir(root: &Path, name: &str) -> PathBuf {
+        let name = files::file_name(name);
+        let mut dir = root.join(&name);
+        let mut n = 1;
+        while dir.exists() {
+            n += 1;
+            dir = root.join(format!("{name}-{n}"));
+        }
+        dir
+    }
+
     fn try_load_subdir(dir: PathBuf, default_handle: &Handle) -> Result<Persona> {
         let modified_time = Self::modified_time(&dir);
 
         let mut image = Err(anyhow!("Persona not found"));
         let mut persona = Err(anyhow!("Persona not found"));
+        let mut png_persona = Err(anyhow!("Persona not found"));
         for entry in (fs::read_dir(&dir)?).flatten() {
             let path = entry.path();
             if path.is_file()
@@ -107,13 +173,17 @@ impl PersonaLoader {
             {
                 match ext {
                     "json" => persona = Self::load_persona(path),
-                    "png" => image = Self::load_image(path),
+                    "png" => {
+                        png_persona = Self::load_card_png(&path);
+                        image = Self::load_image(path);
+                    }
                     _ => (),
                 }
             }
         }
 
-        match persona {
+        // A separate json file takes precedence over a card embedded in the avatar.
+        match persona.or(png_persona) {
             Ok(data) => Ok(Persona::new(
                 data,
                 match image {
@@ -127,20 +197,188 @@ impl PersonaLoader {
         }
     }
 
+    /// A single card PNG, used both as the card and the avatar.
+    fn try_load_card_png(path: PathBuf) -> Result<Persona> {
+        let data = Self::load_card_png(&path)?;
+        Ok(Persona::new(
+            data,
+            Self::load_image(path.clone())?,
+            Self::modified_time(&path),
+            path,
+        ))
+    }
+
+    /// Writes a card back to a persona directory or loose card PNG, keeping
+    /// the layout it was found in. `avatar` must be a PNG, see [`Self::to_png`].
+    pub fn save_card(
+        path: &Path,
+        card: &CardV3,
+        v3: bool,
+        avatar: Option<&[u8]>,
+        subdir: Subdir,
+    ) -> Result<Persona> {
+        if path.as_os_str().is_empty() {
+            return Err(anyhow!("Built-in personas can't be saved"));
+        }
+        let v2 = card.v2(v3);
+        let json = match v3 {
+            true => serde_json::to_string_pretty(card)?,
+            false => serde_json::to_string_pretty(&v2)?,
+        };
+        let with_card = |png: &[u8]| png::with_card(png, &v2, card);
+
+        if path.is_file() {
+            let png = match avatar {
+                Some(avatar) => avatar.to_vec(),
+                None => fs::read(path)?,
+            };
+            write_atomic(path, &with_card(&png)?)?;
+            trace!("Saved card {}", path.display());
+            return Self::try_load_card_png(path.to_path_buf());
+        }
+
+        let files = |ext: &str| -> Vec<PathBuf> {
+            let mut files: Vec<PathBuf> = fs::read_dir(path)
+                .into_iter()
+                .flatten()
+                .flatten()
+                .map(|e| e.path())
+                .filter(|p| p.is_file() && p.extension().is_some_and(|e| e == ext))
+                .collect();
+            files.sort();
+            files
+        };
+        fs::create_dir_all(path)?;
+        let json_file = files("json").into_iter().next();
+        let png_file = files("png").into_iter().next();
+        // A card embedded in the avatar stays there when there is no json file.
+        let embedded = json_file.is_none()
+            && png_file
+                .as_ref()
+                .is_some_and(|p| Self::load_card_png(p).is_ok());
+        match embedded {
+            true => {
+                let png_file = png_file.unwrap_or_else(|| path.join("avatar.png"));
+                let png = match avatar {
+                    Some(avatar) => avatar.to_vec(),
+                    None => fs::read(&png_file)?,
+                };
+                write_atomic(&png_file, &with_card(&png)?)?;
+            }
+            false => {
+                write_atomic(
+                    &json_file.unwrap_or_else(|| path.join("card.json")),
+                    json.as_bytes(),
+                )?;
+                if let Some(avatar) = avatar {
+                    write_atomic(&png_file.unwrap_or_else(|| path.join("avatar.png")), avatar)?;
+                }
+            }
+        }
+        trace!("Saved card {}", path.display());
+        Self::try_load_subdir(path.to_path_buf(), &subdir.default_handle())
+    }
+
+    /// Removes a persona directory or loose card PNG.
+    pub fn delete(path: &Path) -> Result<()> {
+        match path {
+            _ if path.as_os_str().is_empty() => {
+                return Err(anyhow!("Built-in personas can't be deleted"));
+            }
+            _ if path.is_file() => fs::remove_file(path)?,
+            _ => fs::remove_dir_all(path)?,
+        }
+        trace!("Deleted {}", path.display());
+        Ok(())
+    }
+
+    /// Decodes any supported image and encodes it as PNG.
+    pub fn to_png(image: &[u8]) -> Result<Vec<u8>> {
+        let mut png = vec![];
+        load_from_memory(image)?.write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)?;
+        Ok(png)
+    }
+
+    fn load_card_png(path: &PathBuf) -> Result<Rc<dyn CharData>> {
+        Self::parse_persona(&png::card_json(&fs::read(path)?)?)
+    }
+
     fn load_persona(path: PathBuf) -> Result<Rc<dyn CharData>> {
-        let data = fs::read_to_string(&path)?;
-        if let Ok(card) = Card::load_from_json(&data) {
+        Self::parse_persona(&fs::read_to_string(&path)?)
+    }
+
+    fn parse_persona(data: &str) -> Result<Rc<dyn CharData>> {
+        // V3 first, V2 cards ignore the fields they do not know.
+        if let Ok(card) = CardV3::load_from_json(data) {
+            trace!("Loaded V3 card {}", card.name());
+            return Ok(card);
+        }
+        if let Ok(card) = Card::load_from_json(data) {
             trace!("Loaded card {}", card.name());
             return Ok(card);
         }
 
-        let basic = Basic::load_from_json(&data)?;
+        let basic = Basic::load_from_json(data)?;
         trace!("Loaded simple {}", basic.name());
         Ok(basic)
     }
 
     fn load_image(path: PathBuf) -> Result<Handle> {
-        let mut image = Self::crop_to_square(open(path)?.to_rgba8());
+        let image = Self::circle(open(path)?.to_rgba8());
+        let (width, height) = image.dimensions();
+        Ok(Handle::from_rgba(
+            width,
+            height,
+            Bytes::from(image.into_raw()),
+        ))
+    }
+
+    /// Encodes an avatar handle as a circular PNG.
+    pub fn avatar_png(handle: &Handle) -> Result<Vec<u8>> {
+        let image = match handle {
+            Handle::Path(_, path) => Self::circle(open(path)?.to_rgba8()),
+            Handle::Bytes(_, bytes) => Self::circle(load_from_memory(bytes)?.to_rgba8()),
+            Handle::Rgba {
+                width,
+                height,
+                pixels,
+                ..
+            } => ImageBuffer::from_raw(*width, *height, pixels.to_vec())
+                .ok_or(anyhow!("Invalid image buffer"))?,
+        };
+        let mut png = vec![];
+        DynamicImage::ImageRgba8(image)
+            .write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)?;
+        Ok(png)
+    }
+
+    /// The avatar file of the persona at `path` as PNG, re-encoded only when it
+    /// is another format. Personas without a file fall back to `handle`.
+    pub fn original_avatar_png(path: &Path, handle: &Handle) -> Result<Vec<u8>> {
+        let file = match path.is_file() {
+            true => Some(path.to_path_buf()),
+            false => fs::read_dir(path)
+                .into_iter()
+                .flatten()
+                .flatten()
+                .map(|e| e.path())
+                .filter(|p| p.is_file() && p.extension().is_some_and(|e| e == "png"))
+                .min(),
+        };
+        let data = match (file, handle) {
+            (Some(file), _) => fs::read(file)?,
+            (None, Handle::Path(_, path)) => fs::read(path)?,
+            (None, Handle::Bytes(_, bytes)) => bytes.to_vec(),
+            (None, Handle::Rgba { .. }) => return Self::avatar_png(handle),
+        };
+        match png::is_png(&data) {
+            true => Ok(data),
+            false => Self::to_png(&data),
+        }
+    }
+
+    fn circle(image: ImageBuffer<Rgba<u8>, Vec<u8>>) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        let mut image = Self::crop_to_square(image);
 
         let (width, height) = image.dimensions();
         let center_x = width as f64 / 2.0;
@@ -156,12 +394,7 @@ impl PersonaLoader {
                 pixel[3] = 0
             }
         }
-
-        Ok(Handle::from_rgba(
-            width,
-            height,
-            Bytes::from(image.into_raw()),
-        ))
+        image
     }
 
     fn crop_to_square(image: ImageBuffer<Rgba<u8>, Vec<u8>>) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
@@ -174,7 +407,7 @@ impl PersonaLoader {
         crop_imm(&image, x_offset, y_offset, size, size).to_image()
     }
 
-    fn cache_path(subdir: &Subdir) -> PathBuf {
+    pub fn cache_path(subdir: &Subdir) -> PathBuf {
         dirs::cache_dir()
             .map(|mut path| {
                 path.push("fullmoon");
@@ -201,3 +434,241 @@ impl PersonaLoader {
         dest.set_modified(SystemTime::now())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, rc::Rc, time::SystemTime};
+
+    use base64::{Engine, engine::general_purpose::STANDARD};
+    use iced::{
+        advanced::graphics::image::image_rs::{
+            DynamicImage, ImageBuffer, ImageOutputFormat, Rgba, load_from_memory,
+        },
+        widget::image::Handle,
+    };
+    use std::io::Cursor;
+
+    use super::PersonaLoader;
+    use crate::{
+        persona::{
+            Persona,
+            basic::Basic,
+            card::{Card, CharacterBook, Entry},
+            card_v3::CardV3,
+            png,
+        },
+        utils::files::test_dir,
+    };
+
+    fn sample_card() -> Card {
+        let mut card = Card::new("Aria", "A wandering bard who sings to {{user}}.");
+        card.data.first_mes = "Hello {{user}}, care for a song?".to_string();
+        card.data.alternate_greetings = vec!["*tunes her lute*".to_string()];
+        card.data.tags = vec!["fantasy".to_string()];
+        card
+    }
+
+    fn export_and_reload(persona: &Persona, file: &str) -> Persona {
+        let path = test_dir("cards").join(file);
+        fs::write(&path, persona.card_png().unwrap()).unwrap();
+        PersonaLoader::try_load_card_png(path).unwrap()
+    }
+
+    #[test]
+    fn card_round_trips_through_png() {
+        let card = sample_card();
+        let persona = Persona::new(
+            Rc::new(card.clone()),
+            Handle::from_path("assets/char.png"),
+            SystemTime::now(),
+            Default::default(),
+        );
+        let loaded = export_and_reload(&persona, "aria.png");
+        assert_eq!(loaded.name(), "Aria");
+        assert_eq!(
+            loaded.system_prompt(Some("Bob")),
+            persona.system_prompt(Some("Bob"))
+        );
+        assert_eq!(
+            loaded.greetings(Some("Bob")),
+            persona.greetings(Some("Bob"))
+        );
+    }
+
+    #[test]
+    fn card_chunks_hold_the_same_data() {
+        let mut card = sample_card();
+        card.data.character_book = Some(CharacterBook {
+            name: Some("Songs".to_string()),
+            description: None,
+            scan_depth: None,
+            token_budget: None,
+            recursive_scanning: None,
+            extensions: Default::default(),
+            extra: Default::default(),
+            entries: vec![Entry {
+                keys: vec!["lute".to_string()],
+                content: "Her lute was a gift.".to_string(),
+                extensions: Default::default(),
+                enabled: true,
+                insertion_order: 0,
+                case_sensitive: None,
+                name: None,
+                priority: None,
+                id: Some(1),
+                comment: None,
+                selective: None,
+                secondary_keys: None,
+                constant: None,
+                position: None,
+                extra: Default::default(),
+            }],
+        });
+        let avatar = PersonaLoader::avatar_png(&Handle::from_path("assets/char.png")).unwrap();
+        let v3 = CardV3::from(card.clone());
+        let exported = png::with_card(&avatar, &card, &v3).unwrap();
+        // Exporting twice replaces the chunks instead of piling them up.
+        let exported = png::with_card(&exported, &card, &v3).unwrap();
+
+        let chunks = png::text_chunks(&exported).unwrap();
+        assert_eq!(chunks.len(), 2);
+        let chunk = |keyword: &str| -> serde_json::Value {
+            let (_, text) = chunks.iter().find(|(k, _)| k == keyword).unwrap();
+            serde_json::from_slice(&STANDARD.decode(text).unwrap()).unwrap()
+        };
+        let expected = serde_json::to_value(&card).unwrap();
+        assert_eq!(chunk("chara"), expected);
+        assert_eq!(chunk("ccv3"), serde_json::to_value(&v3).unwrap());
+        assert_eq!(chunk("ccv3")["spec"], "chara_card_v3");
+    }
+
+    #[test]
+    fn card_png_keeps_the_original_avatar() {
+        let dir = test_dir("wide");
+        let avatar = ImageBuffer::from_pixel(40, 20, Rgba([200, 30, 30, 255]));
+        let mut jpeg = vec![];
+        DynamicImage::ImageRgba8(avatar.clone())
+            .to_rgb8()
+            .write_to(&mut Cursor::new(&mut jpeg), ImageOutputFormat::Jpeg(90))
+            .unwrap();
+        let card = CardV3::from(sample_card());
+        let avatar = PersonaLoader::to_png(&jpeg).unwrap();
+        let char =
+            PersonaLoader::save_card(&dir, &card, false, Some(&avatar), super::Subdir::Chars)
+                .unwrap();
+
+        let exported = char.card_png().unwrap();
+        assert!(png::is_png(&exported));
+        let image = load_from_memory(&exported).unwrap().to_rgba8();
+        assert_eq!(image.dimensions(), (40, 20));
+        // The export keeps the uncropped original, so the corners stay opaque.
+        assert_eq!(image.get_pixel(0, 0)[3], 255);
+        assert!(png::card_json(&exported).is_ok());
+    }
+
+    #[test]
+    fn basic_is_upgraded_to_a_card() {
+        let persona = Persona::new(
+            Basic::new("Max", "You are {{char}}, talking to {{user}}."),
+            Handle::from_path("assets/char.png"),
+            SystemTime::now(),
+            Default::default(),
+        );
+        let loaded = export_and_reload(&persona, "max.png");
+        assert_eq!(loaded.name(), "Max");
+        assert_eq!(
+            loaded.system_prompt(Some("Bob")),
+            "You are Max, talking to Bob."
+        );
+    }
+
+    #[test]
+    fn saving_keeps_the_spec_and_unknown_fields() {
+        let dir = test_dir("aria");
+        let mut card = serde_json::to_value(sample_card()).unwrap();
+        card["data"]["extensions"]["depth_prompt"] = serde_json::json!({"depth": 4});
+        card["data"]["custom_field"] = serde_json::json!("kept");
+        fs::write(dir.join("aria.json"), card.to_string()).unwrap();
+
+        let persona = PersonaLoader::load_persona_dir(dir.clone(), super::Subdir::Chars).unwrap();
+        let mut edited = persona.card_v3();
+        edited.data.personality = "Cheerful".to_string();
+        let saved = persona.save(&edited, None).unwrap();
+        assert_eq!(saved.card().data.personality, "Cheerful");
+
+        let written: serde_json::Value =
+            serde_json::from_slice(&fs::read(dir.join("aria.json")).unwrap()).unwrap();
+        assert_eq!(written["spec"], Card::SPEC);
+        assert_eq!(written["data"]["extensions"]["depth_prompt"]["depth"], 4);
+        assert_eq!(written["data"]["custom_field"], "kept");
+        assert!(!dir.join("card.json").exists());
+    }
+
+    #[test]
+    fn v2_saves_keep_lore_content_and_v3_fields() {
+        let dir = test_dir("lyra");
+        let mut card = serde_json::to_value(sample_card()).unwrap();
+        card["data"]["character_book"] = serde_json::json!({
+            "entries": [{
+                "keys": ["lute"],
+                "content": "@@depth 2\nHer lute was a gift.",
+                "extensions": {},
+                "enabled": true,
+                "insertion_order": 0
+            }]
+        });
+        fs::write(dir.join("lyra.json"), card.to_string()).unwrap();
+        let written = || -> serde_json::Value {
+            serde_json::from_slice(&fs::read(dir.join("lyra.json")).unwrap()).unwrap()
+        };
+
+        let persona = PersonaLoader::load_persona_dir(dir.clone(), super::Subdir::Chars).unwrap();
+        let mut edited = persona.card_v3();
+        edited.data.personality = "Cheerful".to_string();
+        let persona = persona.save(&edited, None).unwrap();
+        assert_eq!(written()["spec"], Card::SPEC);
+        assert_eq!(
+            written()["data"]["character_book"]["entries"][0]["content"],
+            "@@depth 2\nHer lute was a gift."
+        );
+
+        edited.data.nickname = Some("Ari".to_string());
+        let saved = persona.save(&edited, None).unwrap();
+        assert_eq!(saved.spec(), CardV3::SPEC);
+        assert_eq!(written()["data"]["nickname"], "Ari");
+    }
+
+    #[test]
+    fn users_are_saved_and_deleted() {
+        let dir = test_dir("bob");
+        let card = CardV3::from(Card::new("Bob", "{{user}} is a knight."));
+
+        let user =
+            PersonaLoader::save_card(&dir, &card, false, None, super::Subdir::Users).unwrap();
+        assert_eq!(user.name(), "Bob");
+        assert_eq!(user.card().data.description, "{{user}} is a knight.");
+        assert!(dir.join("card.json").exists());
+
+        PersonaLoader::delete(user.path()).unwrap();
+        assert!(!dir.exists());
+        assert!(PersonaLoader::delete(Persona::default_user().path()).is_err());
+    }
+
+    #[test]
+    fn user_lock_is_kept_in_the_card() {
+        let dir = test_dir("lock");
+        let card = CardV3::from(sample_card());
+        let char =
+            PersonaLoader::save_card(&dir, &card, false, None, super::Subdir::Chars).unwrap();
+        assert_eq!(char.user_lock(), None);
+
+        let locked = char.lock_user(Some("bob")).unwrap();
+        assert_eq!(locked.user_lock().as_deref(), Some("bob"));
+        assert_eq!(locked.card().data.extensions["user_persona"], "bob");
+        assert_eq!(locked.folder(), Some("lock"));
+
+        let unlocked = locked.lock_user(None).unwrap();
+        assert_eq!(unlocked.user_lock(), None);
+        assert_eq!(unlocked.card().data.personality, card.data.personality);
+    }
+}
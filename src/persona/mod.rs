@@ -1,18 +1,43 @@
-use std::{fmt::Debug, ops::Deref, path::PathBuf, rc::Rc, time::SystemTime};
+use std::{
+    fmt::Debug,
+    ops::Deref,
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::SystemTime,
+};
 
 use iced::widget::{Image, image::Handle};
 use log::error;
 
-use crate::persona::{basic::Basic, loader::PersonaLoader};
+use crate::persona::{
+    basic::Basic,
+    card::Card,
+    card_v3::CardV3,
+    loader::{PersonaLoader, Subdir},
+};
 
 mod basic;
-mod card;
+pub mod card;
+pub mod card_v3;
+pub mod charx;
+pub mod lenient;
 pub mod loader;
+mod png;
 
 pub trait CharData {
     fn name(&self) -> &str;
     fn system_prompt(&self, partner_name: Option<&str>) -> String;
     fn greetings(&self, partner_name: Option<&str>) -> Option<Vec<String>>;
+    /// The data as a V2 card, for formats that only know cards.
+    fn card(&self) -> Card;
+    /// The data as a V3 card, upgraded from V2 unless it is one already.
+    fn card_v3(&self) -> CardV3 {
+        self.card().into()
+    }
+    /// Spec the data is saved as, basic personas being upgraded to V2 cards.
+    fn spec(&self) -> &str {
+        Card::SPEC
+    }
 }
 
 #[derive(Clone)]
@@ -74,25 +99,111 @@ impl Persona {
         }
     }
 
-    // pub fn save(&self, path: PathBuf) -> Result<(), Box<dyn Error>> {
-    //     if !path.exists() {
-    //         fs::create_dir_all(&path)?;
-    //     }
-    //
-    //     let config_path = path.join(format!("{}.json", self.name()));
-    //     let content = match &self.ptype {
-    //         PType::Basic(basic) => serde_json::to_string_pretty(basic)?,
-    //         PType::Card(card) => serde_json::to_string_pretty(card)?,
-    //     };
-    //     fs::write(config_path, content)?;
-    //
-    //     Ok(())
-    // }
+    pub fn default_system() -> Self {
+        Self {
+            data: Basic::new("System", ""),
+            image: Handle::from_path("assets/char.png"),
+            modified_time: SystemTime::now(),
+            path: PathBuf::new(),
+        }
+    }
+
+    pub fn default_narrator() -> Self {
+        Self {
+            data: Basic::new("Narrator", ""),
+            image: Handle::from_path("assets/char.png"),
+            modified_time: SystemTime::now(),
+            path: PathBuf::new(),
+        }
+    }
+
+    /// Writes `card` back where the persona was loaded from, in the persona's
+    /// spec unless `card` needs V3, with `avatar` replacing the current image.
+    /// Returns the reloaded persona.
+    pub fn save(&self, card: &CardV3, avatar: Option<&[u8]>) -> anyhow::Result<Persona> {
+        PersonaLoader::save_card(
+            &self.path,
+            card,
+            self.spec() == CardV3::SPEC || card.needs_v3(),
+            avatar,
+            Subdir::Chars,
+        )
+    }
+
+    /// The world book linked to the character, kept like SillyTavern does
+    /// in the card's `world` extension.
+    pub fn world(&self) -> Option<String> {
+        self.extension("world")
+    }
+
+    /// Links `world` to the character, or unlinks it, and saves the card.
+    pub fn link_world(&self, world: Option<&str>) -> anyhow::Result<Persona> {
+        self.set_extension("world", world)
+    }
+
+    /// Folder of the user persona chats with the character default to, kept
+    /// in the card's `user_persona` extension.
+    pub fn user_lock(&self) -> Option<String> {
+        self.extension("user_persona")
+    }
+
+    /// Locks the user persona in `folder` to the character, or unlocks it,
+    /// and saves the card.
+    pub fn lock_user(&self, folder: Option<&str>) -> anyhow::Result<Persona> {
+        self.set_extension("user_persona", folder)
+    }
+
+    fn extension(&self, key: &str) -> Option<String> {
+        self.card()
+            .data
+            .extensions
+            .get(key)
+            .and_then(|w| w.as_str())
+            .filter(|w| !w.is_empty())
+            .map(str::to_string)
+    }
+
+    fn set_extension(&self, key: &str, value: Option<&str>) -> anyhow::Result<Persona> {
+        let mut card = self.card_v3();
+        match value {
+            Some(value) => card.data.extensions.insert(key.to_string(), value.into()),
+            None => card.data.extensions.remove(key),
+        };
+        self.save(&card, None)
+    }
 
     pub fn image(&self) -> Image {
         iced::widget::image(&self.image)
     }
 
+    pub fn avatar_png(&self) -> anyhow::Result<Vec<u8>> {
+        PersonaLoader::avatar_png(&self.image)
+    }
+
+    /// The avatar as it was saved, uncropped, as PNG.
+    pub fn original_avatar_png(&self) -> anyhow::Result<Vec<u8>> {
+        PersonaLoader::original_avatar_png(&self.path, &self.image)
+    }
+
+    /// The original avatar with the card embedded, loadable by other frontends.
+    pub fn card_png(&self) -> anyhow::Result<Vec<u8>> {
+        png::with_card(
+            &self.original_avatar_png()?,
+            &self.data.card(),
+            &self.data.card_v3(),
+        )
+    }
+
+    /// The persona's directory, or its file for a loose PNG card.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The name of the persona's directory or file, None for built-in personas.
+    pub fn folder(&self) -> Option<&str> {
+        self.path.file_name().and_then(|n| n.to_str())
+    }
+
     pub fn modified_time(&self) -> SystemTime {
         self.modified_time
     }
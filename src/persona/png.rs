@@ -0,0 +1,117 @@
+use crate::persona::{card::Card, card_v3::CardV3};
+use anyhow::{Result, anyhow};
+use base64::{Engine, engine::general_purpose::STANDARD};
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+
+/// Keywords of the `tEXt` chunks holding a card, most recent spec first.
+const CARD_KEYWORDS: [&str; 2] = ["ccv3", "chara"];
+
+/// Whether `data` starts with the PNG signature.
+pub fn is_png(data: &[u8]) -> bool {
+    data.starts_with(&SIGNATURE)
+}
+
+/// Reads every `tEXt` chunk as a keyword and its latin-1 text.
+pub fn text_chunks(png: &[u8]) -> Result<Vec<(String, String)>> {
+    if !is_png(png) {
+        return Err(anyhow!("Not a PNG file"));
+    }
+    let mut chunks = vec![];
+    let mut pos = SIGNATURE.len();
+    while pos + 8 <= png.len() {
+        let length = u32::from_be_bytes(png[pos..pos + 4].try_into()?) as usize;
+        let kind = &png[pos + 4..pos + 8];
+        let data = png
+            .get(pos + 8..pos + 8 + length)
+            .ok_or(anyhow!("Truncated PNG chunk"))?;
+        match kind {
+            b"tEXt" => {
+                if let Some(split) = data.iter().position(|b| *b == 0) {
+                    chunks.push((latin1(&data[..split]), latin1(&data[split + 1..])));
+                }
+            }
+            b"IEND" => break,
+            _ => (),
+        }
+        // Length, type, data and CRC.
+        pos += 12 + length;
+    }
+    Ok(chunks)
+}
+
+/// The card JSON embedded in a PNG, decoded from base64.
+pub fn card_json(png: &[u8]) -> Result<String> {
+    let chunks = text_chunks(png)?;
+    let text = CARD_KEYWORDS
+        .iter()
+        .find_map(|keyword| {
+            chunks
+                .iter()
+                .find(|(k, _)| k == keyword)
+                .map(|(_, text)| text)
+        })
+        .ok_or(anyhow!("No card found in PNG"))?;
+    Ok(String::from_utf8(STANDARD.decode(text.trim())?)?)
+}
+
+/// Embeds a card as both a `chara` V2 chunk and a `ccv3` V3 chunk, replacing
+/// any card already there.
+pub fn with_card(png: &[u8], v2: &Card, v3: &CardV3) -> Result<Vec<u8>> {
+    with_text_chunks(
+        png,
+        &[
+            ("chara", STANDARD.encode(serde_json::to_string(v2)?)),
+            ("ccv3", STANDARD.encode(serde_json::to_string(v3)?)),
+        ],
+    )
+}
+
+/// Rewrites `png` with the given `tEXt` chunks right after the header, dropping
+/// older chunks with the same keywords.
+pub fn with_text_chunks(png: &[u8], texts: &[(&str, String)]) -> Result<Vec<u8>> {
+    if !is_png(png) {
+        return Err(anyhow!("Not a PNG file"));
+    }
+    let mut out = SIGNATURE.to_vec();
+    let mut pos = SIGNATURE.len();
+    while pos + 8 <= png.len() {
+        let length = u32::from_be_bytes(png[pos..pos + 4].try_into()?) as usize;
+        let kind = &png[pos + 4..pos + 8];
+        let chunk = png
+            .get(pos..pos + 12 + length)
+            .ok_or(anyhow!("Truncated PNG chunk"))?;
+        let replaced = kind == b"tEXt"
+            && texts
+                .iter()
+                .any(|(keyword, _)| chunk[8..].starts_with(&[keyword.as_bytes(), &[0]].concat()));
+        if !replaced {
+            out.extend_from_slice(chunk);
+        }
+        if kind == b"IHDR" {
+            for (keyword, text) in texts {
+                write_chunk(
+                    &mut out,
+                    b"tEXt",
+                    &[keyword.as_bytes(), &[0], text.as_bytes()].concat(),
+                );
+            }
+        }
+        pos += 12 + length;
+    }
+    Ok(out)
+}
+
+fn write_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(kind);
+    crc.update(data);
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    out.extend_from_slice(&crc.finalize().to_be_bytes());
+}
+
+fn latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| *b as char).collect()
+}
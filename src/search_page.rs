@@ -0,0 +1,297 @@
+use std::{collections::HashSet, path::PathBuf};
+
+use chrono::{DateTime, Local, NaiveDate};
+use iced::{
+    Alignment, Border, Element,
+    Length::Fill,
+    Theme,
+    widget::{checkbox, column, container, keyed, pick_list, row, scrollable, text_input},
+};
+use iced_modern_theme::colors::colors;
+use log::{error, trace};
+use regex::{Regex, RegexBuilder};
+
+use crate::{
+    AppCommand,
+    chat_page::{chat::Chat, session::SessionLoader},
+    message::OwnerType,
+    settings::Settings,
+    utils::widgets::{bold_text, button, text},
+};
+
+const MAX_RESULTS: usize = 200;
+const SNIPPET_CONTEXT: usize = 40;
+const ALL: &str = "All";
+
+#[derive(Debug, Clone)]
+pub enum SearchCommand {
+    Query(String),
+    Regex(bool),
+    Char(String),
+    Owner(String),
+    From(String),
+    To(String),
+}
+
+impl From<SearchCommand> for crate::AppCommand {
+    fn from(search_command: SearchCommand) -> Self {
+        crate::AppCommand::SearchCommand(search_command)
+    }
+}
+
+/// A single saved message, with where to find it again.
+#[derive(Clone)]
+pub struct IndexEntry {
+    pub char_name: String,
+    pub session: PathBuf,
+    pub path: Vec<usize>,
+    owner_type: OwnerType,
+    text: String,
+    time: DateTime<Local>,
+}
+
+struct SearchResult {
+    entry: usize,
+    snippet: String,
+}
+
+pub struct SearchPage {
+    index: Vec<IndexEntry>,
+    chars: Vec<String>,
+    owners: Vec<String>,
+    query: String,
+    regex: bool,
+    char: String,
+    owner: String,
+    from: String,
+    to: String,
+    results: Vec<SearchResult>,
+    error: Option<String>,
+}
+
+impl SearchPage {
+    pub fn new() -> Self {
+        let index = Self::build_index();
+        let mut chars: Vec<String> = index
+            .iter()
+            .map(|e| e.char_name.clone())
+            .collect::<HashSet<String>>()
+            .into_iter()
+            .collect();
+        chars.sort();
+        chars.insert(0, ALL.to_string());
+
+        let mut owners: Vec<String> = OwnerType::ALL.iter().map(|o| o.to_string()).collect();
+        owners.insert(0, ALL.to_string());
+
+        Self {
+            index,
+            chars,
+            owners,
+            query: String::new(),
+            regex: false,
+            char: ALL.to_string(),
+            owner: ALL.to_string(),
+            from: String::new(),
+            to: String::new(),
+            results: vec![],
+            error: None,
+        }
+    }
+
+    fn build_index() -> Vec<IndexEntry> {
+        let mut index = vec![];
+        let sessions = match SessionLoader::all_sessions() {
+            Ok(sessions) => sessions,
+            Err(e) => {
+                error!("{e}");
+                return index;
+            }
+        };
+        for (char_name, session) in sessions {
+            match Chat::read(&session) {
+                Ok(chat) => {
+                    for item in chat.tree_items(&HashSet::new()) {
+                        index.push(IndexEntry {
+                            char_name: char_name.clone(),
+                            session: session.clone(),
+                            path: item.path,
+                            owner_type: item.message.owner_type.clone(),
+                            text: item.message.text.clone(),
+                            time: item.message.time,
+                        })
+                    }
+                }
+                Err(e) => error!("{}: {e}", session.display()),
+            }
+        }
+        trace!("Indexed {} messages", index.len());
+        index
+    }
+
+    pub fn get(&self, idx: usize) -> IndexEntry {
+        self.index[idx].clone()
+    }
+
+    pub fn update(&mut self, search_command: SearchCommand) {
+        match search_command {
+            SearchCommand::Query(query) => self.query = query,
+            SearchCommand::Regex(regex) => self.regex = regex,
+            SearchCommand::Char(char) => self.char = char,
+            SearchCommand::Owner(owner) => self.owner = owner,
+            SearchCommand::From(from) => self.from = from,
+            SearchCommand::To(to) => self.to = to,
+        }
+        self.search();
+    }
+
+    fn search(&mut self) {
+        self.results.clear();
+        self.error = None;
+        if self.query.is_empty() {
+            return;
+        }
+        let re = match self.regex {
+            true => Regex::new(&self.query),
+            false => RegexBuilder::new(&regex::escape(&self.query))
+                .case_insensitive(true)
+                .build(),
+        };
+        let re = match re {
+            Ok(re) => re,
+            Err(e) => {
+                self.error = Some(e.to_string());
+                return;
+            }
+        };
+        let from = NaiveDate::parse_from_str(&self.from, "%Y-%m-%d").ok();
+        let to = NaiveDate::parse_from_str(&self.to, "%Y-%m-%d").ok();
+
+        for (idx, entry) in self.index.iter().enumerate() {
+            let date = entry.time.date_naive();
+            if (self.char != ALL && entry.char_name != self.char)
+                || (self.owner != ALL && entry.owner_type.to_string() != self.owner)
+                || from.is_some_and(|from| date < from)
+                || to.is_some_and(|to| date > to)
+            {
+                continue;
+            }
+            if let Some(m) = re.find(&entry.text) {
+                self.results.push(SearchResult {
+                    entry: idx,
+                    snippet: Self::snippet(&entry.text, m.start(), m.end()),
+                });
+                if self.results.len() >= MAX_RESULTS {
+                    break;
+                }
+            }
+        }
+    }
+
+    fn snippet(text: &str, start: usize, end: usize) -> String {
+        let before: String = text[..start]
+            .chars()
+            .rev()
+            .take(SNIPPET_CONTEXT)
+            .collect::<Vec<char>>()
+            .into_iter()
+            .rev()
+            .collect();
+        let after: String = text[end..].chars().take(SNIPPET_CONTEXT).collect();
+        let snippet = format!(
+            "{}{}{}{}{}",
+            if before.len() < start { "…" } else { "" },
+            before,
+            &text[start..end],
+            after,
+            if after.len() < text.len() - end {
+                "…"
+            } else {
+                ""
+            },
+        );
+        snippet.split_whitespace().collect::<Vec<&str>>().join(" ")
+    }
+
+    pub fn view<'a>(&'a self, settings: &'a Settings) -> Element<'a, AppCommand> {
+        let mut keyed_column = keyed::Column::new().padding(10).spacing(10);
+        for (key, result) in self.results.iter().enumerate() {
+            let entry = &self.index[result.entry];
+            keyed_column = keyed_column.push(
+                key,
+                container(
+                    column![
+                        bold_text(
+                            format!(
+                                "{} · {} · {}",
+                                entry.char_name,
+                                entry.owner_type,
+                                entry.time.format("%B %d, %Y %H:%M")
+                            ),
+                            settings
+                        ),
+                        text(&result.snippet, settings),
+                        button("Open", settings)
+                            .on_press(AppCommand::OpenSearchResult(result.entry)),
+                    ]
+                    .spacing(5),
+                )
+                .padding(10)
+                .width(Fill)
+                .style(Self::box_style),
+            );
+        }
+
+        column![
+            text_input("Search all chats", &self.query)
+                .size(settings.font_size())
+                .on_input(|q| SearchCommand::Query(q).into())
+                .on_paste(|q| SearchCommand::Query(q).into()),
+            row![
+                checkbox("Regex", self.regex)
+                    .size(settings.font_size())
+                    .on_toggle(|r| SearchCommand::Regex(r).into()),
+                pick_list(self.chars.as_slice(), Some(&self.char), |c| {
+                    SearchCommand::Char(c).into()
+                })
+                .text_size(settings.font_size()),
+                pick_list(self.owners.as_slice(), Some(&self.owner), |o| {
+                    SearchCommand::Owner(o).into()
+                })
+                .text_size(settings.font_size()),
+            ]
+            .align_y(Alignment::Center)
+            .spacing(10),
+            row![
+                text_input("From YYYY-MM-DD", &self.from)
+                    .size(settings.font_size())
+                    .on_input(|f| SearchCommand::From(f).into()),
+                text_input("To YYYY-MM-DD", &self.to)
+                    .size(settings.font_size())
+                    .on_input(|t| SearchCommand::To(t).into()),
+            ]
+            .spacing(10),
+            text(
+                match &self.error {
+                    Some(e) => e.clone(),
+                    None => format!("{} results", self.results.len()),
+                },
+                settings
+            ),
+            scrollable(keyed_column)
+                .height(Fill)
+                .width(Fill)
+                .spacing(10),
+        ]
+        .padding(10)
+        .spacing(10)
+        .width(Fill)
+        .into()
+    }
+
+    fn box_style(theme: &Theme) -> iced::widget::container::Style {
+        container::rounded_box(theme)
+            .background(colors::fill::SECONDARY_DARK)
+            .border(Border::default().rounded(12))
+    }
+}
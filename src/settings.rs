@@ -16,7 +16,6 @@ use std::fs;
 
 use crate::{
     AppCommand,
-    persona::Persona,
     utils::widgets::{bold_text, text},
 };
 
@@ -27,7 +26,11 @@ pub enum SettingsChange {
     Temperature(f32),
     MaxTokens(u32),
     Reasoning(bool),
+    Alternatives(u32),
     FontSize(f32),
+    World(String, bool),
+    /// Folder of the selected user persona.
+    User(Option<String>),
 }
 
 impl From<SettingsChange> for crate::AppCommand {
@@ -43,7 +46,15 @@ pub struct Settings {
     temperature: f32,
     max_tokens: u32,
     reasoning: bool,
+    #[serde(default = "Settings::default_alternatives")]
+    alternatives: u32,
     font_size: f32,
+    /// World books attached to every chat.
+    #[serde(default)]
+    worlds: Vec<String>,
+    /// Folder of the selected user persona in the users directory.
+    #[serde(default)]
+    user: Option<String>,
 }
 
 impl Default for Settings {
@@ -54,7 +65,10 @@ impl Default for Settings {
             temperature: 0.5,
             max_tokens: 1000,
             reasoning: false,
+            alternatives: Self::default_alternatives(),
             font_size: 16.0,
+            worlds: vec![],
+            user: None,
         }
     }
 }
@@ -64,7 +78,23 @@ impl Settings {
         self.font_size
     }
 
-    pub fn llm(&self, char: &Persona, user: &Persona) -> Box<dyn LLMProvider> {
+    pub fn alternatives(&self) -> u32 {
+        self.alternatives
+    }
+
+    pub fn worlds(&self) -> &[String] {
+        &self.worlds
+    }
+
+    pub fn user(&self) -> Option<&str> {
+        self.user.as_deref()
+    }
+
+    fn default_alternatives() -> u32 {
+        3
+    }
+
+    pub fn llm(&self, system_prompt: String) -> Box<dyn LLMProvider> {
         LLMBuilder::new()
             .backend(LLMBackend::OpenRouter)
             .api_key(self.api_key.clone())
@@ -72,7 +102,7 @@ impl Settings {
             .temperature(self.temperature)
             .max_tokens(self.max_tokens)
             .reasoning(self.reasoning)
-            .system(char.system_prompt(Some(user.name())))
+            .system(system_prompt)
             .build()
             .expect("Failed to build LLM (Openrouter)")
     }
@@ -173,6 +203,17 @@ impl Settings {
                         checkbox("Reasoning", self.reasoning)
                             .size(self.font_size)
                             .on_toggle(|r| SettingsChange::Reasoning(r).into()),
+                        column![
+                            text(
+                                format! {"Parallel alternatives: {}", self.alternatives},
+                                self
+                            ),
+                            slider(1..=8, self.alternatives, |a| {
+                                SettingsChange::Alternatives(a).into()
+                            })
+                            .width(Fill),
+                        ]
+                        .spacing(5),
                     ]
                     .align_x(Alignment::Center)
                     .spacing(10)
@@ -228,10 +269,25 @@ impl Settings {
                 trace!("Update reasoning: {reasoning}");
                 self.reasoning = reasoning
             }
+            SettingsChange::Alternatives(alternatives) => {
+                trace!("Update alternatives: {alternatives}");
+                self.alternatives = alternatives
+            }
             SettingsChange::FontSize(font_size) => {
                 trace!("Update font size: {font_size}");
                 self.font_size = font_size
             }
+            SettingsChange::World(world, attached) => {
+                trace!("Update global world {world}: {attached}");
+                self.worlds.retain(|w| *w != world);
+                if attached {
+                    self.worlds.push(world)
+                }
+            }
+            SettingsChange::User(user) => {
+                trace!("Update selected user: {user:?}");
+                self.user = user
+            }
         }
 
         if let Err(e) = self.save() {
@@ -0,0 +1,400 @@
+use std::fs;
+
+use anyhow::{Result, anyhow};
+use chrono::Local;
+use iced::{
+    Border, Element,
+    Length::Fill,
+    Theme,
+    widget::{
+        TextEditor, checkbox, column, container,
+        image::Handle,
+        keyed, row, scrollable,
+        text_editor::{Action, Content},
+        text_input,
+    },
+};
+use iced_modern_theme::colors::colors;
+use log::trace;
+
+use crate::{
+    AppCommand,
+    persona::{
+        Persona,
+        card::Card,
+        card_v3::CardV3,
+        loader::{PersonaLoader, Subdir},
+    },
+    settings::Settings,
+    utils::{
+        files,
+        widgets::{bold_text, button, text},
+    },
+};
+
+#[derive(Debug, Clone)]
+pub enum UserCommand {
+    New,
+    Edit(usize),
+    Select(usize),
+    Delete(usize),
+    /// Locks the user to the current char, or unlocks it.
+    LockChar(usize, bool),
+    /// Locks the user to the current chat, or unlocks it.
+    LockChat(usize, bool),
+    Name(String),
+    Description(Action),
+    AvatarPath(String),
+    ReplaceAvatar,
+    Save,
+    Cancel,
+}
+
+impl From<UserCommand> for crate::AppCommand {
+    fn from(user_command: UserCommand) -> Self {
+        crate::AppCommand::UserCommand(user_command)
+    }
+}
+
+/// A user persona being created or edited.
+struct UserEditor {
+    /// None for a new persona.
+    user: Option<Persona>,
+    name: String,
+    description: Content,
+    avatar_path: String,
+    /// PNG data and preview of the replacement avatar.
+    avatar: Option<(Vec<u8>, Handle)>,
+    avatar_error: Option<String>,
+}
+
+/// Lists the user personas, the one selected speaks for the user in chats
+/// unless another one is locked to the char or the chat.
+pub struct UserPage {
+    users: Vec<Persona>,
+    editor: Option<UserEditor>,
+}
+
+impl UserPage {
+    pub fn new() -> Self {
+        let mut up = Self {
+            users: PersonaLoader::load_from_cache(Subdir::Users),
+            editor: None,
+        };
+        up.reorder();
+        up
+    }
+
+    /// Marks the user at `idx` as the most recent one, listed first.
+    pub fn get(&mut self, idx: usize) -> Persona {
+        self.users[idx].set_modified_time();
+        let p = self.users[idx].clone();
+        self.reorder();
+        p
+    }
+
+    pub fn update(&mut self, command: UserCommand) {
+        match command {
+            UserCommand::New => self.editor = Some(UserEditor::new(None)),
+            UserCommand::Edit(idx) => {
+                self.editor = Some(UserEditor::new(Some(self.users[idx].clone())))
+            }
+            UserCommand::Cancel => self.editor = None,
+            command => {
+                if let Some(editor) = &mut self.editor {
+                    editor.update(command)
+                }
+            }
+        }
+    }
+
+    pub fn user(&self, idx: usize) -> &Persona {
+        &self.users[idx]
+    }
+
+    /// Saves the edited user and closes the editor. Returns the saved user.
+    pub fn save(&mut self) -> Result<Persona> {
+        let Some(editor) = &self.editor else {
+            return Err(anyhow!("No user persona is being edited"));
+        };
+        let user = editor.save()?;
+        trace!("Saved user {}", user.name());
+        // Built-in personas are saved to a new directory and replaced by it.
+        let old_path = editor.user.as_ref().map(|u| u.path().to_path_buf());
+        match self
+            .users
+            .iter_mut()
+            .find(|u| Some(u.path()) == old_path.as_deref())
+        {
+            Some(old) => *old = user.clone(),
+            None => self.users.insert(0, user.clone()),
+        }
+        self.editor = None;
+        Ok(user)
+    }
+
+    /// The user being edited, None when creating one.
+    pub fn editing(&self) -> Option<&Persona> {
+        self.editor.as_ref().and_then(|e| e.user.as_ref())
+    }
+
+    /// Deletes the user at `idx` from the disk and the list.
+    pub fn delete(&mut self, idx: usize) -> Result<Persona> {
+        PersonaLoader::delete(self.users[idx].path())?;
+        Ok(self.users.remove(idx))
+    }
+
+    fn reorder(&mut self) {
+        self.users.sort_by_key(|p| p.modified_time());
+        self.users.reverse();
+    }
+
+    fn taken_names(&self) -> Vec<&str> {
+        let editing = self.editing().map(|u| u.path());
+        self.users
+            .iter()
+            .filter(|u| Some(u.path()) != editing)
+            .map(|u| u.name())
+            .collect()
+    }
+
+    pub fn view<'a>(
+        &'a self,
+        settings: &'a Settings,
+        active: &'a Persona,
+        char: &'a Persona,
+        chat_lock: Option<&'a str>,
+    ) -> Element<'a, AppCommand> {
+        if let Some(editor) = &self.editor {
+            return editor.view(settings, &self.taken_names());
+        }
+        let char_lock = char.user_lock();
+        let mut keyed_column = keyed::Column::new().padding(10).spacing(10);
+        for (idx, user) in self.users.iter().enumerate() {
+            let is_active = user.path() == active.path();
+            // Built-in personas have no folder to be locked by.
+            let folder = user.folder();
+            keyed_column = keyed_column.push(
+                idx,
+                container(
+                    row![
+                        user.image().height(100),
+                        column![
+                            bold_text(user.name(), settings),
+                            text(user.card().data.description, settings),
+                            row![
+                                button(if is_active { "Selected" } else { "Select" }, settings)
+                                    .on_press_maybe(
+                                        (!is_active).then_some(UserCommand::Select(idx).into())
+                                    ),
+                                button("Edit", settings).on_press(UserCommand::Edit(idx).into()),
+                                button("Delete", settings)
+                                    .on_press(UserCommand::Delete(idx).into()),
+                            ]
+                            .spacing(10),
+                            row![
+                                checkbox(
+                                    format!("Locked to {}", char.name()),
+                                    folder.is_some() && char_lock.as_deref() == folder
+                                )
+                                .size(settings.font_size())
+                                .text_size(settings.font_size())
+                                .on_toggle_maybe(folder.map(|_| {
+                                    move |locked| UserCommand::LockChar(idx, locked).into()
+                                })),
+                                checkbox(
+                                    "Locked to this chat",
+                                    folder.is_some() && chat_lock == folder
+                                )
+                                .size(settings.font_size())
+                                .text_size(settings.font_size())
+                                .on_toggle_maybe(folder.map(|_| {
+                                    move |locked| UserCommand::LockChat(idx, locked).into()
+                                })),
+                            ]
+                            .spacing(20),
+                        ]
+                        .width(Fill)
+                        .spacing(10)
+                    ]
+                    .width(Fill)
+                    .spacing(10)
+                    .padding(10),
+                )
+                .style(UserPage::box_style),
+            )
+        }
+        column![
+            row![
+                container(bold_text("User personas", settings)).width(Fill),
+                button("New persona", settings).on_press(UserCommand::New.into()),
+            ]
+            .spacing(10),
+            scrollable(keyed_column).height(Fill).width(Fill),
+        ]
+        .padding(10)
+        .spacing(10)
+        .width(Fill)
+        .into()
+    }
+
+    fn box_style(theme: &Theme) -> iced::widget::container::Style {
+        container::rounded_box(theme)
+            .background(colors::fill::SECONDARY_DARK)
+            .border(Border::default().rounded(12))
+    }
+}
+
+impl UserEditor {
+    fn new(user: Option<Persona>) -> Self {
+        let card = user.as_ref().map(|u| u.card());
+        Self {
+            name: card
+                .as_ref()
+                .map(|c| c.data.name.clone())
+                .unwrap_or_default(),
+            description: Content::with_text(
+                card.as_ref().map_or("", |c| c.data.description.as_str()),
+            ),
+            avatar_path: String::new(),
+            avatar: None,
+            avatar_error: None,
+            user,
+        }
+    }
+
+    fn update(&mut self, command: UserCommand) {
+        match command {
+            UserCommand::Name(name) => self.name = name,
+            UserCommand::Description(action) => self.description.perform(action),
+            UserCommand::AvatarPath(path) => self.avatar_path = path,
+            UserCommand::ReplaceAvatar => {
+                match fs::read(self.avatar_path.trim())
+                    .map_err(anyhow::Error::from)
+                    .and_then(|data| PersonaLoader::to_png(&data))
+                {
+                    Ok(avatar) => {
+                        self.avatar = Some((avatar.clone(), Handle::from_bytes(avatar)));
+                        self.avatar_error = None;
+                    }
+                    Err(e) => self.avatar_error = Some(e.to_string()),
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Writes the persona back where it was loaded from, or to a new
+    /// directory for new and built-in personas.
+    fn save(&self) -> Result<Persona> {
+        let name = self.name.trim();
+        let description = self.description.text();
+        let description = description.strip_suffix('\n').unwrap_or(&description);
+        let (mut card, path, v3) = match &self.user {
+            Some(user) if !user.path().as_os_str().is_empty() => (
+                user.card_v3(),
+                user.path().to_path_buf(),
+                user.spec() == CardV3::SPEC,
+            ),
+            _ => (
+                CardV3::from(Card::new(name, description)),
+                PersonaLoader::new_persona_dir(Subdir::Users, name),
+                false,
+            ),
+        };
+        card.data.name = name.to_string();
+        card.data.description = description.to_string();
+        card.data.modification_date = Some(Local::now().timestamp());
+        PersonaLoader::save_card(
+            &path,
+            &card,
+            v3,
+            self.avatar.as_ref().map(|(png, _)| png.as_slice()),
+            Subdir::Users,
+        )
+    }
+
+    /// Problems that prevent saving.
+    fn errors(&self, taken_names: &[&str]) -> Vec<String> {
+        let name = self.name.trim();
+        let mut errors = vec![];
+        if name.is_empty() {
+            errors.push("The name is required".to_string());
+        }
+        if !name.is_empty() && files::file_name(name) != name {
+            errors.push(
+                "The name can't be . or .., end with a dot or contain / \\ : * ? \" < > | \
+                 or control characters"
+                    .to_string(),
+            );
+        }
+        if taken_names
+            .iter()
+            .any(|taken| taken.eq_ignore_ascii_case(name))
+        {
+            errors.push(format!("Another persona is already named {name}"));
+        }
+        errors
+    }
+
+    fn view<'a>(&'a self, settings: &'a Settings, taken_names: &[&str]) -> Element<'a, AppCommand> {
+        let avatar = match (&self.avatar, &self.user) {
+            (Some((_, handle)), _) => iced::widget::image(handle),
+            (None, Some(user)) => user.image(),
+            (None, None) => Persona::default_user().image(),
+        };
+        let mut page = column![
+            row![
+                avatar.height(200),
+                column![
+                    bold_text("Name", settings),
+                    text_input("Name", &self.name)
+                        .size(settings.font_size())
+                        .on_input(|n| UserCommand::Name(n).into()),
+                    row![
+                        text_input("/path/to/avatar.png", &self.avatar_path)
+                            .size(settings.font_size())
+                            .on_input(|p| UserCommand::AvatarPath(p).into())
+                            .on_submit(UserCommand::ReplaceAvatar.into()),
+                        button("Replace avatar", settings)
+                            .on_press(UserCommand::ReplaceAvatar.into()),
+                    ]
+                    .spacing(10),
+                ]
+                .width(Fill)
+                .spacing(10)
+            ]
+            .spacing(10),
+            bold_text("Description, added to the system prompt", settings),
+            TextEditor::new(&self.description)
+                .size(settings.font_size())
+                .height(Fill)
+                .on_action(|a| UserCommand::Description(a).into()),
+        ]
+        .spacing(10)
+        .padding(10);
+
+        let errors = self.errors(taken_names);
+        for problem in &errors {
+            page = page.push(text(problem.clone(), settings));
+        }
+        if let Some(e) = &self.avatar_error {
+            page = page.push(text(format!("Avatar not replaced: {e}"), settings));
+        }
+        let mut save = button("Save", settings);
+        if errors.is_empty() {
+            save = save.on_press(UserCommand::Save.into());
+        }
+        page = page.push(
+            row![
+                save,
+                button("Cancel", settings).on_press(UserCommand::Cancel.into())
+            ]
+            .spacing(10),
+        );
+        container(page)
+            .width(Fill)
+            .style(UserPage::box_style)
+            .into()
+    }
+}
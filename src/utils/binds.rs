@@ -1,5 +1,5 @@
 use iced::{
-    keyboard::{self, key},
+    keyboard::{self, Key, Modifiers, key},
     widget::text_editor::{Binding, KeyPress, Status},
 };
 
@@ -19,3 +19,16 @@ pub fn from_key_press(event: KeyPress) -> Option<Binding<AppCommand>> {
 
     Binding::from_key_press(event)
 }
+
+pub fn from_global_key_press(key: Key, modifiers: Modifiers) -> Option<AppCommand> {
+    match key.as_ref() {
+        keyboard::Key::Character("z") if modifiers.command() => match modifiers.shift() {
+            true => Some(AppCommand::ChatCommand(ChatCommand::Redo)),
+            false => Some(AppCommand::ChatCommand(ChatCommand::Undo)),
+        },
+        keyboard::Key::Character("f") if modifiers.command() => {
+            Some(AppCommand::ChatCommand(ChatCommand::ToggleFind))
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,63 @@
+use std::{fs, io::Write, path::Path};
+
+use anyhow::Result;
+
+/// Writes through a temporary file renamed over `path`, so a crash never
+/// leaves a half written file behind.
+pub fn write_atomic(path: &Path, data: &[u8]) -> Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let mut file = fs::File::create(&tmp)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// `name` with the characters file systems refuse replaced, usable as a file name.
+pub fn file_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    // Windows drops trailing dots and spaces, and ".." is no name at all.
+    let name = name.trim().trim_end_matches('.');
+    match name.is_empty() {
+        true => "_".to_string(),
+        false => name.to_string(),
+    }
+}
+
+/// A fresh directory for a test, unique to the process and the call so
+/// parallel tests and runs never share one.
+#[cfg(test)]
+pub fn test_dir(name: &str) -> std::path::PathBuf {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let dir = std::env::temp_dir()
+        .join("fullmoon-tests")
+        .join(format!(
+            "{}-{}",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        ))
+        .join(name);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn file_names_are_sanitized() {
+        assert_eq!(super::file_name("Aria"), "Aria");
+        assert_eq!(super::file_name("../../etc/passwd"), ".._.._etc_passwd");
+        assert_eq!(super::file_name("Who? <Me>: \"A|B\"*"), "Who_ _Me__ _A_B__");
+        assert_eq!(super::file_name(" .. "), "_");
+        assert_eq!(super::file_name("Line\nbreak."), "Line_break");
+    }
+}
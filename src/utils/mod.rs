@@ -1,2 +1,5 @@
 pub mod binds;
+pub mod files;
+pub mod tokens;
 pub mod widgets;
+pub mod zip;
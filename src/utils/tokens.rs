@@ -0,0 +1,5 @@
+/// Rough token count, about four characters per token for English text with
+/// the usual tokenizers.
+pub fn estimate(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
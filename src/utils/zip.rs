@@ -0,0 +1,99 @@
+use std::io::{Cursor, Read, Write};
+
+use ::zip::{CompressionMethod, ZipArchive, ZipWriter, write::SimpleFileOptions};
+use anyhow::{Result, anyhow};
+
+/// Most bytes unpacked from one archive, packages come from untrusted sources.
+const MAX_UNPACKED: u64 = 256 * 1024 * 1024;
+
+/// Reads every file of a zip archive, directories are skipped.
+pub fn read(zip: &[u8]) -> Result<Vec<(String, Vec<u8>)>> {
+    let mut archive = ZipArchive::new(Cursor::new(zip))?;
+    let mut files = vec![];
+    let mut unpacked = 0;
+    for idx in 0..archive.len() {
+        let mut file = archive.by_index(idx)?;
+        if file.is_dir() {
+            continue;
+        }
+        let name = file.name().to_string();
+        let size = file.size();
+        unpacked += size;
+        if unpacked > MAX_UNPACKED {
+            return Err(anyhow!(
+                "Zip archive unpacks to more than {MAX_UNPACKED} bytes"
+            ));
+        }
+        // The declared size can't be trusted either, one byte more tells it lied.
+        let mut data = vec![];
+        file.by_ref().take(size + 1).read_to_end(&mut data)?;
+        if data.len() as u64 > size {
+            return Err(anyhow!("{name} is larger than its declared size"));
+        }
+        files.push((name, data));
+    }
+    Ok(files)
+}
+
+/// Writes files into a deflated zip archive.
+pub fn write(files: &[(String, Vec<u8>)]) -> Result<Vec<u8>> {
+    let mut zip = ZipWriter::new(Cursor::new(vec![]));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    for (name, data) in files {
+        zip.start_file(name.as_str(), options)?;
+        zip.write_all(data)?;
+    }
+    Ok(zip.finish()?.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    /// Rewrites the uncompressed size of every entry, in the local and central headers.
+    fn declare_size(zip: &mut [u8], size: u32) {
+        for pos in 0..zip.len() - 4 {
+            let offset = match zip[pos..pos + 4] {
+                [0x50, 0x4b, 0x03, 0x04] => 22,
+                [0x50, 0x4b, 0x01, 0x02] => 24,
+                _ => continue,
+            };
+            zip[pos + offset..pos + offset + 4].copy_from_slice(&size.to_le_bytes());
+        }
+    }
+
+    #[test]
+    fn written_archive_reads_back() {
+        let files = vec![
+            (
+                "card.json".to_string(),
+                br#"{"spec":"chara_card_v3"}"#.to_vec(),
+            ),
+            ("assets/icon/images/main.png".to_string(), vec![0; 4096]),
+            ("assets/empty.txt".to_string(), vec![]),
+        ];
+        let zip = super::write(&files).unwrap();
+        assert_eq!(super::read(&zip).unwrap(), files);
+    }
+
+    #[test]
+    fn corrupted_archive_is_refused() {
+        let mut zip = super::write(&[("a.txt".to_string(), b"hello".to_vec())]).unwrap();
+        // Flip a byte of the compressed data, right after the local header and name.
+        zip[30 + 5] ^= 0xff;
+        assert!(super::read(&zip).is_err());
+        assert!(super::read(b"not a zip").is_err());
+    }
+
+    #[test]
+    fn zip_bombs_are_refused() {
+        let bomb = super::write(&[("bomb.bin".to_string(), vec![0; 4 * 1024 * 1024])]).unwrap();
+        assert!(bomb.len() < 64 * 1024);
+
+        let mut understated = bomb.clone();
+        declare_size(&mut understated, 1024);
+        assert!(super::read(&understated).is_err());
+
+        let mut overstated = bomb;
+        declare_size(&mut overstated, u32::MAX - 1);
+        assert!(super::read(&overstated).is_err());
+    }
+}
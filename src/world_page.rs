@@ -0,0 +1,149 @@
+use std::path::PathBuf;
+
+use iced::{
+    Border, Element,
+    Length::Fill,
+    Theme,
+    widget::{checkbox, column, container, row, scrollable, text_input},
+};
+use iced_modern_theme::colors::colors;
+
+use crate::{
+    AppCommand,
+    lore::world::{World, WorldLoader},
+    persona::Persona,
+    settings::Settings,
+    utils::widgets::{bold_text, button, text},
+};
+
+#[derive(Debug, Clone)]
+pub enum WorldCommand {
+    Path(String),
+    Import,
+    Global(String, bool),
+    Char(String, bool),
+    Chat(String, bool),
+}
+
+impl From<WorldCommand> for crate::AppCommand {
+    fn from(world_command: WorldCommand) -> Self {
+        crate::AppCommand::WorldCommand(world_command)
+    }
+}
+
+/// Lists the world books and where they are attached: everywhere, to the
+/// current char or to the current chat.
+pub struct WorldPage {
+    worlds: Vec<World>,
+    path: String,
+    reports: Vec<String>,
+}
+
+impl WorldPage {
+    pub fn new() -> Self {
+        Self {
+            worlds: WorldLoader::load_from_cache(),
+            path: String::new(),
+            reports: vec![],
+        }
+    }
+
+    pub fn path(&self) -> PathBuf {
+        PathBuf::from(self.path.trim())
+    }
+
+    pub fn set_path(&mut self, path: String) {
+        self.path = path
+    }
+
+    pub fn import(&mut self) {
+        match WorldLoader::import(&self.path()) {
+            Ok(world) => {
+                self.reports.push(format!(
+                    "Imported {} with {} entries",
+                    world.name,
+                    world.book.entries.len()
+                ));
+                self.worlds = WorldLoader::load_from_cache();
+            }
+            Err(e) => self.reports.push(e.to_string()),
+        }
+    }
+
+    pub fn view<'a>(
+        &'a self,
+        settings: &'a Settings,
+        char: &'a Persona,
+        chat_worlds: &'a [String],
+    ) -> Element<'a, AppCommand> {
+        let char_world = char.world();
+        let mut worlds = column![].spacing(10);
+        for world in &self.worlds {
+            let name = &world.name;
+            let (global, linked, chat) = (name.clone(), name.clone(), name.clone());
+            worlds = worlds.push(
+                container(
+                    column![
+                        row![
+                            bold_text(&world.name, settings),
+                            text(format!("{} entries", world.book.entries.len()), settings),
+                        ]
+                        .spacing(10),
+                        row![
+                            checkbox("Everywhere", settings.worlds().contains(name))
+                                .size(settings.font_size())
+                                .text_size(settings.font_size())
+                                .on_toggle(move |t| WorldCommand::Global(global.clone(), t).into()),
+                            checkbox(
+                                format!("Linked to {}", char.name()),
+                                char_world.as_ref() == Some(name)
+                            )
+                            .size(settings.font_size())
+                            .text_size(settings.font_size())
+                            .on_toggle(move |t| WorldCommand::Char(linked.clone(), t).into()),
+                            checkbox("This chat", chat_worlds.contains(name))
+                                .size(settings.font_size())
+                                .text_size(settings.font_size())
+                                .on_toggle(move |t| WorldCommand::Chat(chat.clone(), t).into()),
+                        ]
+                        .spacing(20),
+                    ]
+                    .spacing(10),
+                )
+                .padding(10)
+                .width(Fill)
+                .style(Self::box_style),
+            );
+        }
+        for report in self.reports.iter().rev() {
+            worlds = worlds.push(text(report, settings));
+        }
+        column![
+            bold_text("World books", settings),
+            text(
+                "Lorebooks shared by characters and chats, SillyTavern world .json files or V2 lorebooks",
+                settings
+            ),
+            row![
+                text_input("/path/to/world.json", &self.path)
+                    .size(settings.font_size())
+                    .on_input(|p| WorldCommand::Path(p).into())
+                    .on_paste(|p| WorldCommand::Path(p).into())
+                    .on_submit(WorldCommand::Import.into()),
+                button("Import", settings).on_press(WorldCommand::Import.into()),
+            ]
+            .spacing(10),
+            scrollable(worlds).height(Fill).width(Fill),
+        ]
+        .padding(10)
+        .spacing(10)
+        .width(Fill)
+        .into()
+    }
+
+    fn box_style(theme: &Theme) -> iced::widget::container::Style {
+        container::rounded_box(theme)
+            .background(colors::fill::SECONDARY_DARK)
+            .border(Border::default().rounded(12))
+    }
+}